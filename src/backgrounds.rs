@@ -1,20 +1,44 @@
-use crate::player::Player;
+use crate::camera::{half_view, MainCamera};
+use crate::player::{AnimationTimer, Player};
 use crate::quests::{Quest, NPC, NPC_PATH};
-use crate::wfc::{wfc, ProcGen};
-use crate::world::{logical_to_rendering, WorldMap};
+use crate::world::{logical_to_rendering, rendering_to_logical, GameProgress, WorldMap};
+use bevy::ecs::system::SystemParam;
 use bevy::prelude::*;
+use bevy::render::mesh::Indices;
+use bevy::render::render_resource::{Extent3d, PrimitiveTopology, TextureDimension, TextureFormat};
+use bevy::tasks::{AsyncComputeTaskPool, Task};
+use futures_lite::future;
+use rand::{rngs::StdRng, SeedableRng};
+use std::collections::HashSet;
+use waste::biomes::Biome;
+use waste::tiles::{TileInteraction, TileRegistry};
+use waste::wfc::{chunk_rng, chunk_seed, ProcGen, MAP_HEIGHT, MAP_WIDTH, SEED_VAR};
 
 pub(crate) const TILE_SIZE: f32 = 64.;
-pub(crate) const MAP_WIDTH: usize = 20;
-pub(crate) const MAP_HEIGHT: usize = 12;
+/// Size the window opens at, and the UI is laid out for
 pub(crate) const WIN_H: f32 = 768.;
 pub(crate) const WIN_W: f32 = 1280.;
-const DRAW_START_X: f32 = -WIN_W / 2. + TILE_SIZE / 2.;
-const DRAW_START_Y: f32 = WIN_H / 2. - TILE_SIZE / 2.;
-// pub(crate) const LEVEL_WIDTH: f32 = MAP_WIDTH as f32 * TILE_SIZE;
-// pub(crate) const LEVEL_HEIGHT: f32 = MAP_HEIGHT as f32 * TILE_SIZE;
+/// Size of a chunk in the world, whatever size the window is
+pub(crate) const CHUNK_WIDTH: f32 = MAP_WIDTH as f32 * TILE_SIZE;
+pub(crate) const CHUNK_HEIGHT: f32 = MAP_HEIGHT as f32 * TILE_SIZE;
+const DRAW_START_X: f32 = -CHUNK_WIDTH / 2. + TILE_SIZE / 2.;
+const DRAW_START_Y: f32 = CHUNK_HEIGHT / 2. - TILE_SIZE / 2.;
 
-pub(crate) const OVERWORLD_TILESHEET: &str = "backgrounds/overworld_tilesheet.png";
+/// Default number of rings of chunks around the player to generate ahead of time
+pub(crate) const PREFETCH_RADIUS: usize = 2;
+/// Chunks more than this many rings away from the player get their sprites despawned
+pub(crate) const VIEW_RADIUS: usize = 1;
+/// Tiles the player can see in every direction, the fog lifts in a circle this big around them
+pub(crate) const SIGHT_RADIUS: usize = 5;
+/// Color of unseen tiles, as 8 bit RGBA
+const FOG: [u8; 4] = [0, 0, 0, 217];
+/// z of depth sorted things level with the camera. Everything in the overworld stays under 0,
+/// where battles draw their background over it.
+const DEPTH_Z: f32 = -0.75;
+/// Difference in z between depth sorted things a pixel apart in y, capped at `DEPTH_RANGE`
+/// either way so they stay between the ground at -1 and the fog at -0.5
+const DEPTH_PER_PIXEL: f32 = 1e-4;
+const DEPTH_RANGE: f32 = 0.2;
 
 #[derive(Component)]
 pub(crate) struct Tile;
@@ -31,119 +55,386 @@ pub(crate) struct HealingTile;
 #[derive(Component)]
 pub(crate) struct ChestTile;
 
+/// Covers the tiles of a chunk the player hasn't seen yet
+#[derive(Component)]
+pub(crate) struct Fog;
+
+/// Atlas indexes an animated tile cycles through, and which one it's showing
+#[derive(Component, Debug, Clone)]
+pub(crate) struct TileAnimation {
+    pub(crate) frames: Vec<usize>,
+    pub(crate) current: usize,
+}
+
+/// Drawn in front of or behind the other depth sorted things by where it stands,
+/// the lower on screen the closer
+#[derive(Component, Debug, Clone, Copy)]
+pub(crate) struct DepthSorted {
+    /// How far below the center of the sprite it touches the ground
+    pub(crate) base: f32,
+}
+
+/// Which chunk an entity covering a whole chunk, like its ground or fog, was drawn for
+#[derive(Component, Debug, Clone, Copy)]
+pub(crate) struct ChunkPosition(pub(crate) (isize, isize));
+
 #[derive(Component, Debug, Clone)]
 pub(crate) struct Chunk {
     pub(crate) position: (isize, isize),
+    /// What the player can walk on, with decorations in it
     pub(crate) tiles: Vec<Vec<usize>>,
+    /// Flat layer drawn under everything, with plain floor under the decorations
+    pub(crate) ground: Vec<Vec<usize>>,
+    /// Tiles sticking up off the ground, that things can stand behind
+    pub(crate) decoration: Vec<Vec<Option<usize>>>,
+    pub(crate) biome: Biome,
+    /// Name of the prefab stamped into the chunk, if it has one
+    pub(crate) landmark: Option<String>,
+    /// (row, column) of every special tile the player already used up.
+    /// Only the copy in `WorldMap.chunk_components` is kept up to date.
+    pub(crate) used_specials: HashSet<(usize, usize)>,
+    /// (row, column) of every tile the player has had in sight, kept up to date the same way
+    pub(crate) seen: HashSet<(usize, usize)>,
+}
+
+/// Which chunk, and which tile in it, a tile sprite was drawn from
+#[derive(Component, Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct TilePosition {
+    pub(crate) chunk: (isize, isize),
+    pub(crate) row: usize,
+    pub(crate) col: usize,
+}
+
+/// Chunk still being generated on the async compute task pool
+#[derive(Component)]
+pub(crate) struct ChunkTask(Task<Chunk>);
+
+/// How far around the player `expand_map` generates chunks
+pub(crate) struct ChunkPrefetch {
+    /// Every chunk within this many rings of the player's chunk, diagonals included
+    pub(crate) radius: usize,
+}
+
+impl Default for ChunkPrefetch {
+    fn default() -> Self {
+        Self {
+            radius: PREFETCH_RADIUS,
+        }
+    }
+}
+
+/// What `draw_chunk!` spawns chunks with, apart from the `MapAtlas`
+#[derive(SystemParam)]
+pub(crate) struct ChunkDrawing<'w, 's> {
+    commands: Commands<'w, 's>,
+    meshes: ResMut<'w, Assets<Mesh>>,
+    images: ResMut<'w, Assets<Image>>,
+    procgen: Res<'w, ProcGen>,
+}
+
+/// Handles to the overworld tilesheet that every chunk is drawn with
+pub(crate) struct MapAtlas {
+    /// For the sprites of special tiles
+    pub(crate) atlas: Handle<TextureAtlas>,
+    /// For the ground meshes, with the whole tilesheet as its texture
+    pub(crate) material: Handle<ColorMaterial>,
 }
 
 macro_rules! draw_chunk {
-    ($chunk:expr, $commands:expr, $map_atlas_handle:expr) => {
-        // from center of the screen to half a tile from edge
-        // so the tile will never be "cut in half" by edge of screen
+    ($chunk:expr, $commands:expr, $map_atlas:expr, $tiles:expr, $seed:expr, $meshes:expr, $images:expr) => {
         let rendering_center = logical_to_rendering($chunk.position.0, $chunk.position.1);
         // info!("Rendering chunk at {:?}", rendering_center);
-        let mut x = rendering_center.0 + DRAW_START_X;
-        let mut y = rendering_center.1 + DRAW_START_Y;
-
-        for i in 0..$chunk.tiles.len() {
-            for j in 0..$chunk.tiles[i].len() {
-                let tile = $chunk.tiles[i][j];
-                let t = Vec3::new(x, y, -1.);
-                $commands
-                    .spawn_bundle(SpriteSheetBundle {
-                        texture_atlas: $map_atlas_handle.clone(),
-                        transform: Transform {
-                            translation: t,
-                            ..default()
-                        },
-                        sprite: TextureAtlasSprite {
-                            index: tile,
-                            ..default()
-                        },
-                        ..default()
-                    })
-                    .insert(Tile);
-                if tile == 4 {
+
+        // Everything the player can't interact with is one mesh
+        $commands
+            .spawn_bundle(ColorMesh2dBundle {
+                mesh: $meshes.add(ground_mesh(&$chunk, &$tiles)).into(),
+                material: $map_atlas.material.clone(),
+                transform: Transform::from_xyz(rendering_center.0, rendering_center.1, -1.),
+                ..default()
+            })
+            .insert(Tile)
+            .insert(ChunkPosition($chunk.position));
+
+        // Above the terrain and NPCs, so nothing shows through, but under battle backgrounds
+        $commands
+            .spawn_bundle(SpriteBundle {
+                sprite: Sprite {
+                    custom_size: Some(Vec2::new(CHUNK_WIDTH, CHUNK_HEIGHT)),
+                    ..default()
+                },
+                texture: $images.add(fog_image(&$chunk)),
+                transform: Transform::from_xyz(rendering_center.0, rendering_center.1, -0.5),
+                ..default()
+            })
+            .insert(Fog)
+            .insert(ChunkPosition($chunk.position));
+
+        for i in 0..MAP_HEIGHT {
+            for j in 0..MAP_WIDTH {
+                let (x, y) = tile_center(i, j);
+                let position = TilePosition {
+                    chunk: $chunk.position,
+                    row: i,
+                    col: j,
+                };
+                if let Some(decoration) = $chunk.decoration[i][j] {
                     $commands
                         .spawn_bundle(SpriteSheetBundle {
-                            texture_atlas: $map_atlas_handle.clone(),
-                            transform: Transform {
-                                translation: t,
-                                ..default()
-                            },
+                            texture_atlas: $map_atlas.atlas.clone(),
+                            transform: Transform::from_xyz(
+                                rendering_center.0 + x,
+                                rendering_center.1 + y,
+                                0.,
+                            ),
                             sprite: TextureAtlasSprite {
-                                index: tile,
+                                index: decoration,
                                 ..default()
                             },
                             ..default()
                         })
-                        .insert(MonsterTile);
+                        .insert(Tile)
+                        .insert(position)
+                        .insert(DepthSorted {
+                            base: decoration_base(&$chunk, i, j),
+                        });
                 }
-                if tile == 31 {
-                    $commands
-                        .spawn_bundle(SpriteSheetBundle {
-                            texture_atlas: $map_atlas_handle.clone(),
-                            transform: Transform {
-                                translation: t,
-                                ..default()
-                            },
-                            sprite: TextureAtlasSprite {
-                                index: tile,
-                                ..default()
-                            },
-                            ..default()
-                        })
-                        .insert(HealingTile);
+
+                let tile = $chunk.ground[i][j];
+                if !own_sprite(&$tiles, tile) {
+                    continue;
                 }
-                if tile == 33 {
-                    $commands
-                        .spawn_bundle(SpriteSheetBundle {
-                            texture_atlas: $map_atlas_handle.clone(),
-                            transform: Transform {
-                                translation: t,
-                                ..default()
-                            },
-                            sprite: TextureAtlasSprite {
-                                index: tile,
-                                ..default()
-                            },
-                            ..default()
+                let mut sprite = $commands.spawn_bundle(SpriteSheetBundle {
+                    texture_atlas: $map_atlas.atlas.clone(),
+                    transform: Transform::from_xyz(
+                        rendering_center.0 + x,
+                        rendering_center.1 + y,
+                        -1.,
+                    ),
+                    sprite: TextureAtlasSprite {
+                        index: tile,
+                        ..default()
+                    },
+                    ..default()
+                });
+                sprite.insert(Tile).insert(position);
+                // Used specials still get drawn, they just don't do anything anymore
+                if !$chunk.used_specials.contains(&(i, j)) {
+                    match $tiles.interaction(tile) {
+                        TileInteraction::Monster => {
+                            sprite.insert(MonsterTile);
+                        }
+                        TileInteraction::Heal => {
+                            sprite.insert(HealingTile);
+                        }
+                        TileInteraction::Chest => {
+                            sprite.insert(ChestTile);
+                        }
+                        TileInteraction::Npc => {
+                            sprite.insert(NPC {
+                                quest: npc_quest($seed, &position),
+                            });
+                        }
+                        TileInteraction::None => {}
+                    }
+                }
+                if let Some(info) = $tiles.get(tile).filter(|info| !info.frames.is_empty()) {
+                    sprite
+                        .insert(TileAnimation {
+                            frames: info.frames.clone(),
+                            current: 0,
                         })
-                        .insert(ChestTile);
+                        .insert(AnimationTimer(Timer::from_seconds(info.frame_time, true)));
                 }
-
-                x += TILE_SIZE;
             }
-            x = rendering_center.0 + DRAW_START_X;
-            y -= TILE_SIZE;
         }
     };
 }
 
+/// Quest of the NPC on the tile at `position`, the same every time its chunk gets drawn
+fn npc_quest(seed: u64, position: &TilePosition) -> Quest {
+    let (x, y) = position.chunk;
+    let mut rng = chunk_rng(
+        chunk_seed(seed, x, y),
+        position.row as isize,
+        position.col as isize,
+    );
+    Quest::random_from(&mut rng)
+}
+
+/// Check if a tile gets a sprite of its own instead of going in the ground mesh,
+/// because the player can interact with it or it's animated
+fn own_sprite(tiles: &TileRegistry, tile: usize) -> bool {
+    tiles.interaction(tile) != TileInteraction::None
+        || tiles.get(tile).is_some_and(|info| !info.frames.is_empty())
+}
+
+/// Split `tiles` into the ground and decoration layers of a chunk whose plain ground is `floor`
+fn layers(
+    tiles: &[Vec<usize>],
+    floor: usize,
+    registry: &TileRegistry,
+) -> (Vec<Vec<usize>>, Vec<Vec<Option<usize>>>) {
+    let is_decoration = |t: usize| registry.get(t).is_some_and(|info| info.decoration);
+    let ground = tiles
+        .iter()
+        .map(|line| {
+            line.iter()
+                .map(|&t| if is_decoration(t) { floor } else { t })
+                .collect()
+        })
+        .collect();
+    let decoration = tiles
+        .iter()
+        .map(|line| {
+            line.iter()
+                .map(|&t| Some(t).filter(|&t| is_decoration(t)))
+                .collect()
+        })
+        .collect();
+    (ground, decoration)
+}
+
+/// How far below the center of the decoration at (row, col) the object it's part of stands,
+/// counting the decorations right under it as the same object, so the top half of a car wreck
+/// sorts by the bottom of the wreck
+fn decoration_base(chunk: &Chunk, row: usize, col: usize) -> f32 {
+    let below = (row + 1..MAP_HEIGHT)
+        .take_while(|&r| chunk.decoration[r][col].is_some())
+        .count();
+    TILE_SIZE / 2. + below as f32 * TILE_SIZE
+}
+
+/// Center of the tile at (row, col), relative to the center of its chunk.
+/// From the center of the screen to half a tile from the edge,
+/// so the tile will never be "cut in half" by the edge of the screen.
+fn tile_center(row: usize, col: usize) -> (f32, f32) {
+    (
+        DRAW_START_X + col as f32 * TILE_SIZE,
+        DRAW_START_Y - row as f32 * TILE_SIZE,
+    )
+}
+
+/// One quad per ground tile of `chunk` that isn't special, centered on the middle of the chunk,
+/// with UVs into the whole tilesheet
+fn ground_mesh(chunk: &Chunk, tiles: &TileRegistry) -> Mesh {
+    let (columns, rows) = (tiles.columns as f32, tiles.rows as f32);
+    // Pulled in by half a pixel of the sheet, so the sampler doesn't bleed in the tiles next to it
+    let inset = Vec2::new(0.5 / (columns * TILE_SIZE), 0.5 / (rows * TILE_SIZE));
+    let half = TILE_SIZE / 2.;
+
+    let mut positions: Vec<[f32; 3]> = Vec::new();
+    let mut uvs: Vec<[f32; 2]> = Vec::new();
+    let mut indices: Vec<u32> = Vec::new();
+    for (i, line) in chunk.ground.iter().enumerate() {
+        for (j, &tile) in line.iter().enumerate() {
+            if own_sprite(tiles, tile) {
+                continue;
+            }
+            let (x, y) = tile_center(i, j);
+            let left = (tile % tiles.columns) as f32 / columns + inset.x;
+            let top = (tile / tiles.columns) as f32 / rows + inset.y;
+            let right = left + 1. / columns - 2. * inset.x;
+            let bottom = top + 1. / rows - 2. * inset.y;
+
+            let first = positions.len() as u32;
+            // Top left, top right, bottom right, bottom left
+            positions.extend([
+                [x - half, y + half, 0.],
+                [x + half, y + half, 0.],
+                [x + half, y - half, 0.],
+                [x - half, y - half, 0.],
+            ]);
+            uvs.extend([[left, top], [right, top], [right, bottom], [left, bottom]]);
+            // Both triangles counterclockwise
+            indices.extend([first, first + 3, first + 2, first, first + 2, first + 1]);
+        }
+    }
+
+    let normals = vec![[0., 0., 1.]; positions.len()];
+    let mut mesh = Mesh::new(PrimitiveTopology::TriangleList);
+    mesh.insert_attribute(Mesh::ATTRIBUTE_POSITION, positions);
+    mesh.insert_attribute(Mesh::ATTRIBUTE_NORMAL, normals);
+    mesh.insert_attribute(Mesh::ATTRIBUTE_UV_0, uvs);
+    mesh.set_indices(Some(Indices::U32(indices)));
+    mesh
+}
+
+/// Fog over `chunk`, one pixel per tile. It gets stretched over the whole chunk,
+/// which blurs the edge of the fog across a tile.
+fn fog_image(chunk: &Chunk) -> Image {
+    let mut image = Image::new_fill(
+        Extent3d {
+            width: MAP_WIDTH as u32,
+            height: MAP_HEIGHT as u32,
+            depth_or_array_layers: 1,
+        },
+        TextureDimension::D2,
+        &FOG,
+        TextureFormat::Rgba8UnormSrgb,
+    );
+    for &(row, col) in chunk.seen.iter() {
+        clear_fog(&mut image, row, col);
+    }
+    image
+}
+
+/// Make the fog over the tile at (row, col) see-through
+fn clear_fog(image: &mut Image, row: usize, col: usize) {
+    image.data[(row * MAP_WIDTH + col) * 4 + 3] = 0;
+}
+
 pub(crate) fn init_background(
-    mut commands: Commands,
     asset_server: Res<AssetServer>,
     mut texture_atlases: ResMut<Assets<TextureAtlas>>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
     mut world: ResMut<WorldMap>,
-    procgen: Res<ProcGen>,
+    drawing: ChunkDrawing,
 ) {
-    let rules = procgen.rules.clone();
+    let ChunkDrawing {
+        mut commands,
+        mut meshes,
+        mut images,
+        procgen,
+    } = drawing;
+    info!(
+        "Generating world from seed {} (set {} to replay it)",
+        procgen.seed, SEED_VAR
+    );
 
-    let starting_chunk = Chunk {
-        position: (0, 0),
-        tiles: wfc(None, rules),
-    };
-
-    let map_handle = asset_server.load(OVERWORLD_TILESHEET);
-    let map_atlas = TextureAtlas::from_grid(map_handle, Vec2::splat(TILE_SIZE), 7, 6);
+    // Generate the first chunk right away so there is something to stand on
+    let starting_chunk = generate_chunk(&procgen, 0, 0);
 
-    let map_atlas_handle = texture_atlases.add(map_atlas);
+    let tiles = &procgen.tiles;
+    let map_handle = asset_server.load(tiles.sheet.as_str());
+    let map_atlas = TextureAtlas::from_grid(
+        map_handle.clone(),
+        Vec2::splat(TILE_SIZE),
+        tiles.columns,
+        tiles.rows,
+    );
+    let map_atlas = MapAtlas {
+        atlas: texture_atlases.add(map_atlas),
+        material: materials.add(ColorMaterial::from(map_handle)),
+    };
 
     let entity = commands.spawn().insert(starting_chunk.clone()).id();
     world.add_to_world(starting_chunk.clone(), entity, 0, 0);
 
-    draw_chunk!(starting_chunk, commands, map_atlas_handle);
+    draw_chunk!(
+        starting_chunk,
+        commands,
+        map_atlas,
+        procgen.tiles,
+        procgen.seed,
+        meshes,
+        images
+    );
+    world.drawn.insert((0, 0));
+    commands.insert_resource(map_atlas);
+
+    // NPC quests come from the world seed too, so a replayed world hands out the same quests
+    let mut npc_rng = StdRng::seed_from_u64(procgen.seed);
 
     // Spawn a few NPCs at fixed locations
     commands
@@ -153,7 +444,10 @@ pub(crate) fn init_background(
             ..default()
         })
         .insert(NPC {
-            quest: Quest::random(),
+            quest: Quest::random_from(&mut npc_rng),
+        })
+        .insert(DepthSorted {
+            base: TILE_SIZE / 2.,
         });
 
     commands
@@ -163,7 +457,10 @@ pub(crate) fn init_background(
             ..default()
         })
         .insert(NPC {
-            quest: Quest::random(),
+            quest: Quest::random_from(&mut npc_rng),
+        })
+        .insert(DepthSorted {
+            base: TILE_SIZE / 2.,
         });
 
     commands
@@ -173,227 +470,274 @@ pub(crate) fn init_background(
             ..default()
         })
         .insert(NPC {
-            quest: Quest::random(),
+            quest: Quest::random_from(&mut npc_rng),
+        })
+        .insert(DepthSorted {
+            base: TILE_SIZE / 2.,
         });
 }
 
+/// Generate the chunk at logical position (x, y)
+pub(crate) fn generate_chunk(procgen: &ProcGen, x: isize, y: isize) -> Chunk {
+    let layout = procgen.generate(x, y);
+    let (ground, decoration) = layers(&layout.tiles, layout.floor, &procgen.tiles);
+    Chunk {
+        position: (x, y),
+        tiles: layout.tiles,
+        ground,
+        decoration,
+        biome: layout.biome,
+        landmark: layout.landmark,
+        used_specials: HashSet::new(),
+        seen: HashSet::new(),
+    }
+}
+
+/// List every chunk within `radius` rings of (x, y), diagonals included.
+///
+/// Closer rings come first, and within a ring chunks in the direction of `heading` come first.
+fn prefetch_order(x: isize, y: isize, radius: usize, heading: Vec2) -> Vec<(isize, isize)> {
+    let radius = radius as isize;
+    let mut positions: Vec<(isize, isize)> = Vec::new();
+    for dy in -radius..=radius {
+        for dx in -radius..=radius {
+            if (dx, dy) != (0, 0) {
+                positions.push((dx, dy));
+            }
+        }
+    }
+
+    let ahead =
+        |&(dx, dy): &(isize, isize)| heading.dot(Vec2::new(dx as f32, dy as f32).normalize());
+    positions.sort_by(|a, b| {
+        let ring_a = a.0.abs().max(a.1.abs());
+        let ring_b = b.0.abs().max(b.1.abs());
+        ring_a
+            .cmp(&ring_b)
+            .then_with(|| ahead(b).total_cmp(&ahead(a)))
+    });
+
+    positions
+        .into_iter()
+        .map(|(dx, dy)| (x + dx, y + dy))
+        .collect()
+}
+
+/// Start generating any chunks around the player that do not exist yet.
+///
+/// Generation runs on the async compute task pool, `finish_chunks` draws
+/// each chunk once its task is done.
 pub(crate) fn expand_map(
     mut commands: Commands,
-    asset_server: Res<AssetServer>,
-    mut texture_atlases: ResMut<Assets<TextureAtlas>>,
     mut world: ResMut<WorldMap>,
     procgen: Res<ProcGen>,
-    mut player_query: Query<&mut Player>,
+    prefetch: Res<ChunkPrefetch>,
+    player_query: Query<&Player>,
 ) {
     // check for collision
     if player_query.is_empty() {
         error!("Couldn't find player");
+        return;
     }
 
-    let rules = procgen.rules.clone();
-
-    let player = player_query.single_mut();
-    let player_chunk_pos = player.current_chunk;
-    // These unwraps could panic if the player goes off the map?
-    // Get the chunk the player is in
-    let pc_x = player_chunk_pos.0;
-    let pc_y = player_chunk_pos.1;
-    let player_chunk = world.get_chunk(pc_x, pc_y).unwrap();
-
-    let map_handle = asset_server.load(OVERWORLD_TILESHEET);
-    let map_atlas = TextureAtlas::from_grid(map_handle, Vec2::splat(TILE_SIZE), 7, 6);
-
-    let map_atlas_handle = texture_atlases.add(map_atlas);
-
-    let east_chunk = world.chunk_ids.get(&(pc_x + 1, pc_y));
-    match east_chunk {
-        Some(_) => (),
-        None => {
-            // Prevent the system from trying to generate a chunk here again
-            let (x, y) = (player_chunk_pos.0 + 1, player_chunk_pos.1);
-            world.chunk_ids.insert((x, y), 0);
-            // Generate seeding vector
-            let mut seed: Vec<(usize, (usize, usize))> = Vec::new();
-            for i in 0..MAP_HEIGHT {
-                seed.push((player_chunk.tiles[i][MAP_WIDTH - 1], (i, 0)));
-            }
-            // if world.get_chunk(x+1, y).is_some(){
-            //     for i in 0..MAP_HEIGHT{
-            //         seed.push((world.get_chunk(x+1, y).unwrap().tiles[i][0], (i, MAP_WIDTH - 1)));
-            //     }
-            // }
-            // if world.get_chunk(x, y+1).is_some(){
-            //     for i in 0..MAP_WIDTH{
-            //         seed.push((world.get_chunk(x, y+1).unwrap().tiles[0][i], (MAP_HEIGHT - 1, i)));
-            //     }
-            // }
-            // if world.get_chunk(x, y-1).is_some(){
-            //     for i in 0..MAP_WIDTH{
-            //         seed.push((world.get_chunk(x, y-1).unwrap().tiles[MAP_HEIGHT - 1][i], (0, i)));
-            //     }
-            // }
-            let new_chunk = Chunk {
-                position: (x, y),
-                tiles: wfc(Some(seed), rules.clone()),
-                // tiles: wfc(None),
-            };
+    let player = player_query.single();
+    let (pc_x, pc_y) = player.current_chunk;
+
+    let thread_pool = AsyncComputeTaskPool::get();
+    for (x, y) in prefetch_order(pc_x, pc_y, prefetch.radius, player.heading) {
+        // Chunks don't depend on each other, so every missing one can start right away
+        if world.is_generated_or_pending(x, y) {
+            continue;
+        }
+
+        let procgen = procgen.clone();
+        let task = thread_pool.spawn(async move { generate_chunk(&procgen, x, y) });
+        // Mark it pending so the system doesn't try to generate a chunk here again
+        world.pending.insert((x, y));
+        commands.spawn().insert(ChunkTask(task));
+    }
+}
+
+/// Add chunks whose generation tasks have finished to the world.
+///
+/// `stream_chunks` draws them once the player is close enough.
+pub(crate) fn finish_chunks(
+    mut commands: Commands,
+    mut world: ResMut<WorldMap>,
+    mut tasks: Query<(Entity, &mut ChunkTask)>,
+) {
+    for (entity, mut task) in tasks.iter_mut() {
+        if let Some(new_chunk) = future::block_on(future::poll_once(&mut task.0)) {
+            let (x, y) = new_chunk.position;
             // info!("New chunk generated at {:?}", new_chunk.position);
-            // info!("World: {:?}", world.chunk_ids);
-            let entity = commands.spawn().insert(new_chunk.clone()).id();
-            // Add to world before drawing, so there is no chance it being redrawn because it's not in the world
-            world.add_to_world(new_chunk.clone(), entity, x, y);
-            draw_chunk!(new_chunk, commands, map_atlas_handle);
+            if let Some(landmark) = &new_chunk.landmark {
+                info!("Landmark {} generated at {:?}", landmark, (x, y));
+            }
+            commands
+                .entity(entity)
+                .remove::<ChunkTask>()
+                .insert(new_chunk.clone());
+            world.pending.remove(&(x, y));
+            world.add_to_world(new_chunk, entity, x, y);
         }
     }
+}
 
-    let south_chunk = world
-        .chunk_ids
-        .get(&(player_chunk_pos.0, player_chunk_pos.1 + 1));
-    match south_chunk {
-        Some(_) => (),
-        None => {
-            // Prevent the system from trying to generate another chunk here while we're drawing this one
-            let (x, y) = (player_chunk_pos.0, player_chunk_pos.1 + 1);
-            world.chunk_ids.insert((x, y), 0);
-            // Generate seeding vector
-            let mut seed: Vec<(usize, (usize, usize))> = Vec::new();
-            for i in 0..MAP_WIDTH {
-                seed.push((player_chunk.tiles[0][i], (MAP_HEIGHT - 1, i)));
+/// Draw generated chunks within `VIEW_RADIUS` of the player and despawn the sprites of
+/// chunks that are farther away. Their data stays in the `WorldMap`, so they can be
+/// drawn again just like they were left.
+pub(crate) fn stream_chunks(
+    mut world: ResMut<WorldMap>,
+    map_atlas: Res<MapAtlas>,
+    drawing: ChunkDrawing,
+    player_query: Query<&Player>,
+    tile_query: Query<(Entity, &TilePosition)>,
+    layer_query: Query<(Entity, &ChunkPosition)>,
+) {
+    let ChunkDrawing {
+        mut commands,
+        mut meshes,
+        mut images,
+        procgen,
+    } = drawing;
+    if player_query.is_empty() {
+        error!("Couldn't find player");
+        return;
+    }
+
+    let (pc_x, pc_y) = player_query.single().current_chunk;
+    let radius = VIEW_RADIUS as isize;
+    let in_view = |(x, y): (isize, isize)| (x - pc_x).abs().max((y - pc_y).abs()) <= radius;
+
+    let far: HashSet<(isize, isize)> = world
+        .drawn
+        .iter()
+        .copied()
+        .filter(|&pos| !in_view(pos))
+        .collect();
+    if !far.is_empty() {
+        for (entity, position) in tile_query.iter() {
+            if far.contains(&position.chunk) {
+                commands.entity(entity).despawn();
+            }
+        }
+        for (entity, position) in layer_query.iter() {
+            if far.contains(&position.0) {
+                commands.entity(entity).despawn();
             }
-            // if world.get_chunk(x, y+1).is_some(){
-            //     for i in 0..MAP_WIDTH{
-            //         seed.push((world.get_chunk(x, y+1).unwrap().tiles[0][i], (0, i)));
-            //     }
-            // }
-            // if world.get_chunk(x+1, y).is_some(){
-            //     for i in 0..MAP_HEIGHT{
-            //         seed.push((world.get_chunk(x+1, y).unwrap().tiles[i][0], (i, MAP_WIDTH - 1)));
-            //     }
-            // }
-            // if world.get_chunk(x-1, y).is_some(){
-            //     for i in 0..MAP_HEIGHT{
-            //         seed.push((world.get_chunk(x-1, y).unwrap().tiles[i][MAP_WIDTH - 1], (i, 0)));
-            //     }
-            // }
-            let new_chunk = Chunk {
-                position: (x, y),
-                tiles: wfc(Some(seed), rules.clone()),
-                // tiles: wfc(None),
-            };
-            // info!("New chunk generated at {:?}", new_chunk.position);
-            // info!("World: {:?}", world.chunk_ids);
-            // draw_chunk!(new_chunk, commands, map_atlas_handle);
-            let entity = commands.spawn().insert(new_chunk.clone()).id();
-            world.add_to_world(new_chunk.clone(), entity, x, y);
-            draw_chunk!(new_chunk, commands, map_atlas_handle);
         }
+        world.drawn.retain(|&pos| in_view(pos));
     }
 
-    let west_chunk = world
-        .chunk_ids
-        .get(&(player_chunk_pos.0 - 1, player_chunk_pos.1));
-    match west_chunk {
-        Some(_) => (),
-        None => {
-            // Prevent the system from trying to generate another chunk here while we're drawing this one
-            let (x, y) = (player_chunk_pos.0 - 1, player_chunk_pos.1);
-            world.chunk_ids.insert((x, y), 0);
-            // Generate seeding vector
-            let mut seed: Vec<(usize, (usize, usize))> = Vec::new();
-            for i in 0..MAP_HEIGHT {
-                seed.push((player_chunk.tiles[i][0], (i, MAP_WIDTH - 1)));
+    for y in pc_y - radius..=pc_y + radius {
+        for x in pc_x - radius..=pc_x + radius {
+            if world.drawn.contains(&(x, y)) {
+                continue;
+            }
+            if let Some(chunk) = world.get_chunk(x, y) {
+                draw_chunk!(
+                    chunk,
+                    commands,
+                    map_atlas,
+                    procgen.tiles,
+                    procgen.seed,
+                    meshes,
+                    images
+                );
+                world.drawn.insert((x, y));
             }
-            // if world.get_chunk(x-1, y).is_some(){
-            //     for i in 0..MAP_HEIGHT{
-            //         seed.push((world.get_chunk(x-1, y).unwrap().tiles[i][MAP_WIDTH-1], (i, 0)));
-            //     }
-            // }
-            // if world.get_chunk(x, y+1).is_some(){
-            //     for i in 0..MAP_WIDTH{
-            //         seed.push((world.get_chunk(x, y+1).unwrap().tiles[0][i], (MAP_HEIGHT-1, i)));
-            //     }
-            // }
-            // if world.get_chunk(x, y-1).is_some(){
-            //     for i in 0..MAP_WIDTH{
-            //         seed.push((world.get_chunk(x, y-1).unwrap().tiles[MAP_HEIGHT-1][i], (0, i)));
-            //     }
-            // }
-            let new_chunk = Chunk {
-                position: (x, y),
-                tiles: wfc(Some(seed), rules.clone()),
-                // tiles: wfc(None),
-            };
-            // info!("New chunk generated at {:?}", new_chunk.position);
-            // info!("World: {:?}", world.chunk_ids);
-            // draw_chunk!(new_chunk, commands, map_atlas_handle);
-            let entity = commands.spawn().insert(new_chunk.clone()).id();
-            world.add_to_world(new_chunk.clone(), entity, x, y);
-            draw_chunk!(new_chunk, commands, map_atlas_handle);
         }
     }
+}
+
+/// Lift the fog from every tile within `SIGHT_RADIUS` of the player,
+/// counting the chunks they see for the first time
+pub(crate) fn lift_fog(
+    mut world: ResMut<WorldMap>,
+    mut game_progress: ResMut<GameProgress>,
+    mut images: ResMut<Assets<Image>>,
+    player_query: Query<&Transform, With<Player>>,
+    fog_query: Query<(&ChunkPosition, &Handle<Image>), With<Fog>>,
+) {
+    if player_query.is_empty() {
+        error!("Couldn't find player");
+        return;
+    }
 
-    let north_chunk = world
-        .chunk_ids
-        .get(&(player_chunk_pos.0, player_chunk_pos.1 - 1));
-    match north_chunk {
-        Some(_) => {}
-        None => {
-            // Prevent the system from trying to generate another chunk here while we're drawing this one
-            let (x, y) = (player_chunk_pos.0, player_chunk_pos.1 - 1);
-            world.chunk_ids.insert((x, y), 0);
-            // Generate seeding vector
-            let mut seed: Vec<(usize, (usize, usize))> = Vec::new();
-            for i in 0..MAP_WIDTH {
-                seed.push((player_chunk.tiles[MAP_HEIGHT - 1][i], (0, i)));
+    let pt = player_query.single();
+    let (revealed, discovered) = world.reveal(pt.translation.x, pt.translation.y, SIGHT_RADIUS);
+    game_progress.chunks_discovered += discovered;
+    if revealed.is_empty() {
+        return;
+    }
+
+    for (position, handle) in fog_query.iter() {
+        let mut in_chunk = revealed.iter().filter(|p| p.chunk == position.0).peekable();
+        // Only touch fog that changed, every image that does gets sent to the GPU again
+        if in_chunk.peek().is_none() {
+            continue;
+        }
+        if let Some(image) = images.get_mut(handle) {
+            for p in in_chunk {
+                clear_fog(image, p.row, p.col);
             }
-            // if world.get_chunk(x, y-1).is_some(){
-            //     for i in 0..MAP_WIDTH{
-            //         seed.push((world.get_chunk(x, y-1).unwrap().tiles[MAP_HEIGHT-1][i], (MAP_HEIGHT-1, i)));
-            //     }
-            // }
-            // if world.get_chunk(x+1, y).is_some(){
-            //     for i in 0..MAP_HEIGHT{
-            //         seed.push((world.get_chunk(x+1, y).unwrap().tiles[i][0], (i, MAP_WIDTH-1)));
-            //     }
-            // }
-            // if world.get_chunk(x-1, y).is_some(){
-            //     for i in 0..MAP_HEIGHT{
-            //         seed.push((world.get_chunk(x-1, y).unwrap().tiles[i][MAP_WIDTH-1], (i, 0)));
-            //     }
-            // }
-            let new_chunk = Chunk {
-                position: (x, y),
-                tiles: wfc(Some(seed), rules),
-                // tiles: wfc(None),
-            };
-            // info!("New chunk generated at {:?}", new_chunk.position);
-            // info!("World: {:?}", world.chunk_ids);
-            // draw_chunk!(new_chunk, commands, map_atlas_handle);
-            let entity = commands.spawn().insert(new_chunk.clone()).id();
-            world.add_to_world(new_chunk.clone(), entity, x, y);
-            draw_chunk!(new_chunk, commands, map_atlas_handle);
         }
     }
 }
 
-// macro_rules! set_seed_ {
-//     ($x: expr, $y:expr, $dir:expr) => {
-//         let mut seed: Vec<(usize, (usize, usize))> = Vec::new();
-//         let player_north = world.get_north(x, y);
-//         let player_south = world.get_south(x, y);
-//         let player_east = world.get_east(x, y);
-//         let player_west = world.get_west(x, y);
-//         if dir = 0{ // when we go to the east
-//             seed.push((chunk.tiles[i][MAP_WIDTH - 1], (i, MAP_WIDTH - 1)))
-//             match player_north {
-//                 Some(chunk) => {
-//                     for i in 0..MAP_WIDTH{
-//                         seed.push((chunk.tiles[MAP_HEIGHT - 1][i], (MAP_HEIGHT - 1, i)));
-//                     }
-//                 },
-//                 None => (),
-//             }
-
-//         }
-//     };
-// }
+/// Step the animated tiles of every chunk on screen along to their next frame,
+/// timed the same way `animate_sprite` times the player's
+pub(crate) fn animate_tiles(
+    time: Res<Time>,
+    camera: Query<(&Transform, &OrthographicProjection), With<MainCamera>>,
+    mut tiles: Query<(
+        &TilePosition,
+        &mut TextureAtlasSprite,
+        &mut AnimationTimer,
+        &mut TileAnimation,
+    )>,
+) {
+    if camera.is_empty() {
+        error!("Found no camera...?");
+        return;
+    }
+
+    // A pixel in from the edges, so chunks that only touch the edge of the screen are left out
+    let (ct, projection) = camera.single();
+    let center = ct.translation.truncate();
+    let half = half_view(projection) - Vec2::ONE;
+    let low = rendering_to_logical(center.x - half.x, center.y - half.y);
+    let high = rendering_to_logical(center.x + half.x, center.y + half.y);
+    let on_screen =
+        |(x, y): (isize, isize)| (low.0..=high.0).contains(&x) && (low.1..=high.1).contains(&y);
+
+    for (position, mut sprite, mut timer, mut animation) in tiles.iter_mut() {
+        if !on_screen(position.chunk) {
+            continue;
+        }
+        timer.tick(time.delta());
+        if timer.just_finished() {
+            animation.current = (animation.current + 1) % animation.frames.len();
+            sprite.index = animation.frames[animation.current];
+        }
+    }
+}
+
+/// Set the z of everything depth sorted from where it stands, so whatever is lower on screen
+/// gets drawn in front. Measured from the camera, so it works however far out the player goes.
+pub(crate) fn sort_depth(
+    camera: Query<&Transform, With<MainCamera>>,
+    mut sorted: Query<(&mut Transform, &DepthSorted), Without<MainCamera>>,
+) {
+    if camera.is_empty() {
+        error!("Found no camera...?");
+        return;
+    }
+
+    let camera_y = camera.single().translation.y;
+    for (mut transform, depth) in sorted.iter_mut() {
+        let feet = transform.translation.y - depth.base - camera_y;
+        transform.translation.z =
+            DEPTH_Z + (-feet * DEPTH_PER_PIXEL).clamp(-DEPTH_RANGE, DEPTH_RANGE);
+    }
+}
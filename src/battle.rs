@@ -1,5 +1,6 @@
 use crate::backgrounds::Tile;
 use crate::camera::{MenuCamera, SlidesCamera};
+use crate::layout::{vh, vw};
 use crate::monster::{
     get_monster_sprite_for_type, Boss, Defense, Element, Enemy, Health, Level, MonsterStats,
     PartyMonster, SelectedMonster, Strength,
@@ -186,8 +187,8 @@ pub(crate) fn setup_battle_stats(
                 align_self: AlignSelf::FlexEnd,
                 position_type: PositionType::Absolute,
                 position: UiRect {
-                    top: Val::Px(5.0),
-                    left: Val::Px(15.0),
+                    top: vh(5.0),
+                    left: vw(15.0),
                     ..default()
                 },
                 ..default()
@@ -223,8 +224,8 @@ pub(crate) fn setup_battle_stats(
                 align_self: AlignSelf::FlexEnd,
                 position_type: PositionType::Absolute,
                 position: UiRect {
-                    top: Val::Px(40.0),
-                    left: Val::Px(15.0),
+                    top: vh(40.0),
+                    left: vw(15.0),
                     ..default()
                 },
                 ..default()
@@ -257,8 +258,8 @@ pub(crate) fn setup_battle_stats(
                 align_self: AlignSelf::FlexEnd,
                 position_type: PositionType::Absolute,
                 position: UiRect {
-                    top: Val::Px(5.0),
-                    right: Val::Px(15.0),
+                    top: vh(5.0),
+                    right: vw(15.0),
                     ..default()
                 },
                 ..default()
@@ -295,8 +296,8 @@ pub(crate) fn setup_battle_stats(
                 align_self: AlignSelf::FlexEnd,
                 position_type: PositionType::Absolute,
                 position: UiRect {
-                    top: Val::Px(40.0),
-                    right: Val::Px(15.0),
+                    top: vh(40.0),
+                    right: vw(15.0),
                     ..default()
                 },
                 ..default()
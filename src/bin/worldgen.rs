@@ -0,0 +1,485 @@
+// Generate part of the overworld without starting the game, to try out WFC inputs
+// and to check in CI that generation still works.
+//
+//     cargo run --release --no-default-features --bin worldgen -- --seed 1234 --chunks -2,-2..2,2 --png world.png
+//
+// It only uses the world generation library, so without the default `game` feature
+// it builds without Bevy or the audio and input libraries the game needs.
+//
+// Chunks are printed as a grid of tile numbers unless `--png` is given, and a report of how long
+// generation took and how many contradictions it ran into goes to stderr. Exits with an error if
+// any chunk could not be generated at all, or with `--strict` if any edges had to be relaxed.
+//
+// `--steps <x>,<y>` instead shows every step of collapsing that one chunk, as a grid of how many
+// options each cell has left, or with `--png <folder>` as one picture per step.
+#![deny(unsafe_code)]
+#![deny(unreachable_code)]
+
+use image::{imageops, Rgba, RgbaImage};
+use std::collections::HashMap;
+use std::env;
+use std::fs::create_dir_all;
+use std::path::{Path, PathBuf};
+use std::process::exit;
+use std::time::{Duration, Instant};
+use waste::prefabs::PREFABS;
+use waste::wfc::{
+    init_seed, ChunkLayout, Contradiction, ProcGen, Step, WfcFrame, WfcStats, MAP_HEIGHT,
+    MAP_WIDTH, WFC_INPUTS,
+};
+
+const USAGE: &str = "usage: worldgen [--seed <n>] [--chunks <x0>,<y0>..<x1>,<y1>] [--rules <dir>] [--prefabs <dir>]
+                [--png <file>] [--strict]
+       worldgen --steps <x>,<y> [--seed <n>] [--rules <dir>] [--prefabs <dir>] [--png <dir>]
+
+  --seed    world seed, defaults to $WASTE_SEED or a random one
+  --chunks  inclusive range of chunks to generate, defaults to -1,-1..1,1
+  --rules   folder to read WFC inputs from, defaults to assets/backgrounds/wfc_inputs
+  --prefabs folder to read landmark prefabs from, defaults to assets/backgrounds/prefabs
+  --png     write a picture of the chunks using the overworld tilesheet instead of printing them
+  --strict  also fail if any chunk had to relax its edges
+  --steps   show every step of collapsing one chunk, with `--png` as a folder for one picture per step";
+
+/// Steps `--steps` shows before giving up on a board that keeps backtracking
+const MAX_STEPS: usize = 5000;
+
+/// Lowest and highest chunk positions, both included
+type ChunkRange = ((isize, isize), (isize, isize));
+
+/// Everything that can be set from the command line
+struct Options {
+    seed: u64,
+    /// Chunks to output
+    chunks: ChunkRange,
+    rules: PathBuf,
+    prefabs: PathBuf,
+    png: Option<PathBuf>,
+    strict: bool,
+    /// Chunk to show every step of
+    steps: Option<(isize, isize)>,
+}
+
+fn main() {
+    // Rule and tile paths are relative to the repository, like when the game runs
+    let options = parse_args(env::args().skip(1).collect()).unwrap_or_else(|e| {
+        eprintln!("{}\n\n{}", e, USAGE);
+        exit(2);
+    });
+
+    let (procgen, skipped) = ProcGen::new(&options.rules, &options.prefabs, options.seed);
+    for problem in &skipped {
+        eprintln!("{}", problem);
+    }
+
+    if let Some(chunk) = options.steps {
+        if !show_steps(&procgen, chunk, options.png.as_deref()) {
+            exit(1);
+        }
+        return;
+    }
+
+    let start = Instant::now();
+    let chunks = generate(&procgen, options.chunks);
+    let elapsed = start.elapsed();
+
+    match &options.png {
+        Some(path) => {
+            if let Err(e) = write_png(&procgen, &chunks, options.chunks, path) {
+                eprintln!("Couldn't write {}: {}", path.display(), e);
+                exit(1);
+            }
+        }
+        None => print_ascii(&chunks, options.chunks),
+    }
+
+    let failed = report(&procgen, &chunks, elapsed);
+    if failed.filled > 0 || (options.strict && (failed.relaxed > 0 || !skipped.is_empty())) {
+        exit(1);
+    }
+}
+
+fn parse_args(args: Vec<String>) -> Result<Options, String> {
+    let mut options = Options {
+        seed: init_seed(),
+        chunks: ((-1, -1), (1, 1)),
+        rules: PathBuf::from(WFC_INPUTS),
+        prefabs: PathBuf::from(PREFABS),
+        png: None,
+        strict: false,
+        steps: None,
+    };
+
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().ok_or(format!("`{}` is missing a value", arg));
+        match arg.as_str() {
+            "--seed" => {
+                let seed = value()?;
+                options.seed = seed
+                    .parse()
+                    .map_err(|_| format!("`{}` is not a valid seed", seed))?;
+            }
+            "--chunks" => options.chunks = parse_range(&value()?)?,
+            "--rules" => options.rules = PathBuf::from(value()?),
+            "--prefabs" => options.prefabs = PathBuf::from(value()?),
+            "--png" => options.png = Some(PathBuf::from(value()?)),
+            "--strict" => options.strict = true,
+            "--steps" => {
+                let chunk = value()?;
+                options.steps = Some(parse_range(&format!("{}..{}", chunk, chunk))?.0);
+            }
+            "-h" | "--help" => {
+                println!("{}", USAGE);
+                exit(0);
+            }
+            _ => return Err(format!("unknown argument `{}`", arg)),
+        }
+    }
+    Ok(options)
+}
+
+/// Read a `<x0>,<y0>..<x1>,<y1>` chunk range, in either order
+fn parse_range(range: &str) -> Result<ChunkRange, String> {
+    let invalid = || format!("`{}` is not a chunk range like -1,-1..1,1", range);
+    let position = |s: &str| -> Result<(isize, isize), String> {
+        let (x, y) = s.split_once(',').ok_or_else(invalid)?;
+        Ok((
+            x.trim().parse().map_err(|_| invalid())?,
+            y.trim().parse().map_err(|_| invalid())?,
+        ))
+    };
+    let (from, to) = range.split_once("..").ok_or_else(invalid)?;
+    let (from, to) = (position(from)?, position(to)?);
+    Ok((
+        (from.0.min(to.0), from.1.min(to.1)),
+        (from.0.max(to.0), from.1.max(to.1)),
+    ))
+}
+
+/// Generate every chunk in the range. Chunks only depend on the seed and their position,
+/// so they come out the same as in the game whatever else gets generated.
+fn generate(
+    procgen: &ProcGen,
+    ((x0, y0), (x1, y1)): ChunkRange,
+) -> HashMap<(isize, isize), ChunkLayout> {
+    let mut chunks: HashMap<(isize, isize), ChunkLayout> = HashMap::new();
+    for y in y0..=y1 {
+        for x in x0..=x1 {
+            chunks.insert((x, y), procgen.generate(x, y));
+        }
+    }
+    chunks
+}
+
+/// Print the chunks stitched together as rows of tile numbers, north at the top,
+/// the same way WFC input boards are written
+fn print_ascii(chunks: &HashMap<(isize, isize), ChunkLayout>, ((x0, y0), (x1, y1)): ChunkRange) {
+    for y in (y0..=y1).rev() {
+        for row in 0..MAP_HEIGHT {
+            let line = (x0..=x1)
+                .flat_map(|x| chunks[&(x, y)].tiles[row].iter())
+                .map(|t| format!("{:>2}", t))
+                .collect::<Vec<String>>()
+                .join(" ");
+            println!("{}", line);
+        }
+    }
+}
+
+/// Draw the chunks stitched together with the overworld tilesheet, north at the top
+fn write_png(
+    procgen: &ProcGen,
+    chunks: &HashMap<(isize, isize), ChunkLayout>,
+    ((x0, y0), (x1, y1)): ChunkRange,
+    path: &Path,
+) -> Result<(), image::ImageError> {
+    let sheet = Tilesheet::load(procgen)?;
+    let (chunks_w, chunks_h) = ((x1 - x0 + 1) as u32, (y1 - y0 + 1) as u32);
+    let mut out = RgbaImage::new(
+        chunks_w * MAP_WIDTH as u32 * sheet.tile_w,
+        chunks_h * MAP_HEIGHT as u32 * sheet.tile_h,
+    );
+    for ((x, y), chunk) in chunks {
+        // Chunk's top left corner, in tiles
+        let left = (x - x0) as u32 * MAP_WIDTH as u32;
+        let top = (y1 - y) as u32 * MAP_HEIGHT as u32;
+        for (row, line) in chunk.tiles.iter().enumerate() {
+            for (col, t) in line.iter().enumerate() {
+                sheet.draw(&mut out, *t, left + col as u32, top + row as u32);
+            }
+        }
+    }
+    out.save(path)
+}
+
+/// Overworld tilesheet, cut up the way the tile registry says
+struct Tilesheet {
+    image: RgbaImage,
+    columns: usize,
+    tile_w: u32,
+    tile_h: u32,
+}
+
+impl Tilesheet {
+    fn load(procgen: &ProcGen) -> Result<Self, image::ImageError> {
+        let tiles = &procgen.tiles;
+        let image = image::open(format!("assets/{}", tiles.sheet))?.to_rgba8();
+        Ok(Self {
+            tile_w: image.width() / tiles.columns as u32,
+            tile_h: image.height() / tiles.rows as u32,
+            columns: tiles.columns,
+            image,
+        })
+    }
+
+    /// Draw tile `t` onto `out` at column `x` and row `y`, counted in tiles
+    fn draw(&self, out: &mut RgbaImage, t: usize, x: u32, y: u32) {
+        let (sheet_col, sheet_row) = ((t % self.columns) as u32, (t / self.columns) as u32);
+        let tile = imageops::crop_imm(
+            &self.image,
+            sheet_col * self.tile_w,
+            sheet_row * self.tile_h,
+            self.tile_w,
+            self.tile_h,
+        );
+        imageops::replace(
+            out,
+            &tile.to_image(),
+            (x * self.tile_w) as i64,
+            (y * self.tile_h) as i64,
+        );
+    }
+
+    /// Paint the tile at column `x` and row `y` one color, or only a frame around its inside
+    fn paint(&self, out: &mut RgbaImage, color: Rgba<u8>, x: u32, y: u32, frame: bool) {
+        let width = (self.tile_w / 8).max(1);
+        for dy in 0..self.tile_h {
+            for dx in 0..self.tile_w {
+                let border = dx < width
+                    || dy < width
+                    || dx >= self.tile_w - width
+                    || dy >= self.tile_h - width;
+                if border || !frame {
+                    out.put_pixel(x * self.tile_w + dx, y * self.tile_h + dy, color);
+                }
+            }
+        }
+    }
+}
+
+/// Collapse the chunk at (x, y) one step at a time, the same way the game's first attempt
+/// at it goes, and print or draw every step. Returns whether the board got solved.
+fn show_steps(procgen: &ProcGen, (x, y): (isize, isize), png: Option<&Path>) -> bool {
+    let mut stepper = procgen.stepper(x, y);
+
+    let sheet = match png {
+        Some(folder) => match create_dir_all(folder)
+            .map_err(image::ImageError::from)
+            .and_then(|_| Tilesheet::load(procgen))
+        {
+            Ok(sheet) => Some((sheet, folder)),
+            Err(e) => {
+                eprintln!("Couldn't draw steps into {}: {}", folder.display(), e);
+                return false;
+            }
+        },
+        None => None,
+    };
+
+    let mut backtracks = 0;
+    let mut last = None;
+    for number in 1..=MAX_STEPS {
+        let frame = match stepper.step() {
+            Some(frame) => frame,
+            None => break,
+        };
+        if let Step::Backtracked(_) = frame.step {
+            backtracks += 1;
+        }
+
+        match &sheet {
+            Some((sheet, folder)) => {
+                let path = folder.join(format!("step-{:04}.png", number));
+                if let Err(e) = draw_frame(sheet, &frame).save(&path) {
+                    eprintln!("Couldn't write {}: {}", path.display(), e);
+                    return false;
+                }
+            }
+            None => print_frame(number, &frame),
+        }
+        last = Some((number, frame.step));
+    }
+
+    match last {
+        Some((number, step)) => {
+            eprintln!(
+                "chunk ({}, {}): {} after {} step(s), {} backtrack(s)",
+                x,
+                y,
+                describe(step),
+                number,
+                backtracks
+            );
+            if number == MAX_STEPS && step != Step::Solved {
+                eprintln!("gave up after {} steps", MAX_STEPS);
+            }
+            step == Step::Solved
+        }
+        None => false,
+    }
+}
+
+/// Cell a step emptied and the neighbor that emptied it, if it ran into a contradiction
+fn contradiction(step: Step) -> Option<Contradiction> {
+    match step {
+        Step::Backtracked(c) | Step::Failed(c) => Some(c),
+        Step::Collapsed | Step::Solved => None,
+    }
+}
+
+fn describe(step: Step) -> String {
+    let at = |c: Contradiction| match c.cause {
+        Some(cause) => format!("contradiction at {:?} next to {:?}", c.cell, cause),
+        None => format!("contradiction at {:?}", c.cell),
+    };
+    match step {
+        Step::Collapsed => "collapsed".to_string(),
+        Step::Backtracked(c) => format!("backtracked, {}", at(c)),
+        Step::Solved => "solved".to_string(),
+        Step::Failed(c) => format!("failed, {}", at(c)),
+    }
+}
+
+/// Print how many options each cell has left, `.` once it is down to one.
+/// The cell a contradiction emptied shows as `XX` and the neighbor that emptied it as `!!`.
+fn print_frame(number: usize, frame: &WfcFrame) {
+    println!("step {}: {}", number, describe(frame.step));
+    let contradiction = contradiction(frame.step);
+    for (row, line) in frame.entropy.iter().enumerate() {
+        let line = line
+            .iter()
+            .enumerate()
+            .map(|(col, entropy)| match contradiction {
+                Some(c) if c.cell == (row, col) => "XX".to_string(),
+                Some(c) if c.cause == Some((row, col)) => "!!".to_string(),
+                _ if *entropy == 1 => " .".to_string(),
+                _ => format!("{:>2}", entropy),
+            })
+            .collect::<Vec<String>>()
+            .join(" ");
+        println!("{}", line);
+    }
+    println!();
+}
+
+/// Draw cells that are down to one option as their tile and the rest as a heatmap,
+/// from blue for two options left to red for the most any cell has. The cell a contradiction emptied
+/// gets a red frame and the neighbor that emptied it a yellow one.
+fn draw_frame(sheet: &Tilesheet, frame: &WfcFrame) -> RgbaImage {
+    let mut out = RgbaImage::new(
+        MAP_WIDTH as u32 * sheet.tile_w,
+        MAP_HEIGHT as u32 * sheet.tile_h,
+    );
+    // Biomes only use some of the tiles, so scale to what is left instead of `frame.options`
+    let most = frame.entropy.iter().flatten().copied().max().unwrap_or(1);
+    for (row, line) in frame.entropy.iter().enumerate() {
+        for (col, entropy) in line.iter().enumerate() {
+            let (x, y) = (col as u32, row as u32);
+            match frame.tiles[row][col] {
+                Some(t) => sheet.draw(&mut out, t, x, y),
+                None => {
+                    let heat = (*entropy - 1) as f32 / (most - 1).max(1) as f32;
+                    let color = Rgba([(255. * heat) as u8, 40, (255. * (1. - heat)) as u8, 255]);
+                    sheet.paint(&mut out, color, x, y, false);
+                }
+            }
+        }
+    }
+    if let Some(c) = contradiction(frame.step) {
+        let (row, col) = c.cell;
+        sheet.paint(
+            &mut out,
+            Rgba([255, 0, 0, 255]),
+            col as u32,
+            row as u32,
+            true,
+        );
+        if let Some((row, col)) = c.cause {
+            sheet.paint(
+                &mut out,
+                Rgba([255, 220, 0, 255]),
+                col as u32,
+                row as u32,
+                true,
+            );
+        }
+    }
+    out
+}
+
+/// Chunks that did not come out cleanly
+struct Failures {
+    relaxed: usize,
+    filled: usize,
+}
+
+/// Print how generation went to stderr
+fn report(
+    procgen: &ProcGen,
+    chunks: &HashMap<(isize, isize), ChunkLayout>,
+    elapsed: Duration,
+) -> Failures {
+    let mut positions = chunks.keys().copied().collect::<Vec<(isize, isize)>>();
+    positions.sort_unstable_by_key(|&(x, y)| (y, x));
+
+    let mut total = WfcStats::default();
+    let mut failed = Failures {
+        relaxed: 0,
+        filled: 0,
+    };
+    for (x, y) in positions {
+        let chunk = &chunks[&(x, y)];
+        total.add(&chunk.stats);
+        if let Some(landmark) = &chunk.landmark {
+            eprintln!("chunk ({}, {}): landmark {}", x, y, landmark);
+        }
+        if chunk.carved > 0 {
+            eprintln!(
+                "chunk ({}, {}): changed {} tile(s) to connect it",
+                x, y, chunk.carved
+            );
+        } else if chunk.regenerations > 0 {
+            eprintln!(
+                "chunk ({}, {}): generated again {} time(s) to connect it",
+                x, y, chunk.regenerations
+            );
+        }
+        if chunk.filled {
+            failed.filled += 1;
+            eprintln!("chunk ({}, {}): could not be generated, filled in", x, y);
+        } else if chunk.relaxations > 0 {
+            failed.relaxed += 1;
+            eprintln!(
+                "chunk ({}, {}): relaxed its edges {} time(s)",
+                x, y, chunk.relaxations
+            );
+        }
+    }
+
+    eprintln!("seed {}", procgen.seed);
+    eprintln!(
+        "{} chunk(s) in {:?}, {:?} of it in WFC",
+        chunks.len(),
+        elapsed,
+        total.elapsed
+    );
+    eprintln!(
+        "{} attempt(s), {} step(s), {} propagation(s), {} contradiction(s), {} backtrack(s)",
+        total.attempts, total.steps, total.propagations, total.contradictions, total.backtracks
+    );
+    eprintln!(
+        "{} chunk(s) relaxed, {} chunk(s) filled in",
+        failed.relaxed, failed.filled
+    );
+    failed
+}
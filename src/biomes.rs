@@ -0,0 +1,91 @@
+use crate::element::Element;
+use crate::wfc::chunk_seed;
+use rand::seq::SliceRandom;
+use rand::Rng;
+
+/// Number of chunks across one cell of biome noise.
+/// Bigger means bigger biomes.
+const BIOME_SCALE: f64 = 4.;
+
+/// Kind of land a chunk is, picks the WFC rules it is generated from
+/// and the monsters that live there
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Biome {
+    Desert,
+    Swamp,
+    City,
+}
+
+impl Biome {
+    pub const ALL: [Biome; 3] = [Biome::Desert, Biome::Swamp, Biome::City];
+
+    /// Name of the biome's folder in `assets/backgrounds/wfc_inputs/`
+    pub fn name(&self) -> &'static str {
+        match self {
+            Biome::Desert => "desert",
+            Biome::Swamp => "swamp",
+            Biome::City => "city",
+        }
+    }
+
+    /// Monster elements that can be encountered in this biome
+    pub fn elements(&self) -> &'static [Element] {
+        match self {
+            Biome::Desert => &[Element::Scav, Element::Ember, Element::Rad],
+            Biome::Swamp => &[Element::Flood, Element::Filth, Element::Growth],
+            Biome::City => &[Element::Robot, Element::Clean, Element::Scav],
+        }
+    }
+
+    /// Monster elements that come out in this biome at night, with how common each is
+    pub fn night_elements(&self) -> &'static [(Element, usize)] {
+        match self {
+            Biome::Desert => &[(Element::Rad, 3), (Element::Scav, 2), (Element::Ember, 1)],
+            Biome::Swamp => &[(Element::Filth, 3), (Element::Flood, 2), (Element::Rad, 1)],
+            Biome::City => &[(Element::Robot, 3), (Element::Scav, 2), (Element::Filth, 1)],
+        }
+    }
+
+    /// Pick the element of a monster encountered in this biome, by day or by night
+    pub fn random_element<R: Rng>(&self, rng: &mut R, night: bool) -> Element {
+        if night {
+            self.night_elements()
+                .choose_weighted(rng, |(_, weight)| *weight)
+                .unwrap()
+                .0
+        } else {
+            *self.elements().choose(rng).unwrap()
+        }
+    }
+}
+
+/// Get the biome of the chunk at logical position (x, y).
+///
+/// Every biome gets its own smooth noise field over the chunk grid, and the
+/// biome with the highest value wins, so biomes come in blobs that can border any other biome.
+pub fn biome_at(seed: u64, x: isize, y: isize) -> Biome {
+    let (nx, ny) = (x as f64 / BIOME_SCALE, y as f64 / BIOME_SCALE);
+    let mut best = (f64::MIN, Biome::ALL[0]);
+    for (i, biome) in Biome::ALL.iter().enumerate() {
+        // Offset the seed so the biomes don't share a noise field
+        let value = value_noise(seed.wrapping_add(i as u64 + 1), nx, ny);
+        if value > best.0 {
+            best = (value, *biome);
+        }
+    }
+    best.1
+}
+
+/// Smoothly interpolated random values on an integer lattice, in `0..1`
+fn value_noise(seed: u64, x: f64, y: f64) -> f64 {
+    let (x0, y0) = (x.floor(), y.floor());
+    let lattice =
+        |lx: f64, ly: f64| chunk_seed(seed, lx as isize, ly as isize) as f64 / u64::MAX as f64;
+    // Smoothstep, so the noise has no creases along the lattice lines
+    let fade = |t: f64| t * t * (3. - 2. * t);
+    let (tx, ty) = (fade(x - x0), fade(y - y0));
+
+    let top = lattice(x0, y0) * (1. - tx) + lattice(x0 + 1., y0) * tx;
+    let bottom = lattice(x0, y0 + 1.) * (1. - tx) + lattice(x0 + 1., y0 + 1.) * tx;
+    top * (1. - ty) + bottom * ty
+}
@@ -1,12 +1,9 @@
-use crate::backgrounds::{
-    Tile,
-    TILE_SIZE,
-    // CHUNK_HEIGHT, CHUNK_WIDTH
-    WIN_H,
-    WIN_W,
-};
+use crate::backgrounds::{Tile, TILE_SIZE, WIN_H, WIN_W};
 use crate::player::Player;
+use crate::world::{logical_to_rendering, rendering_to_logical, PooledText, TextBuffer};
+use crate::GameState;
 use bevy::prelude::*;
+use iyes_loopless::prelude::*;
 
 #[derive(Component)]
 pub(crate) struct MainCamera;
@@ -34,9 +31,25 @@ pub(crate) const CAMERA_Z_VALUE: f32 = 100.;
 #[derive(Component)]
 pub(crate) struct BattleCamera;
 
+/// How far the player can get from the middle of the screen before the smooth camera follows
+const DEAD_ZONE: Vec2 = Vec2::new(3. * TILE_SIZE, 2. * TILE_SIZE);
+/// How quickly the smooth camera catches up with the player, the higher the snappier
+const CAMERA_EASING: f32 = 5.;
+
+/// How the overworld camera keeps up with the player
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub(crate) enum CameraMode {
+    /// Stays on the player's screen, and jumps to the next one when they walk off it
+    #[default]
+    ScreenFlip,
+    /// Follows the player around once they get far enough from the middle of the screen
+    SmoothFollow,
+}
+
 pub(crate) fn move_camera(
-    mut player: Query<&mut Player>,
-    pt_query: Query<&Transform, With<Player>>,
+    time: Res<Time>,
+    mode: Res<CameraMode>,
+    player: Query<&Transform, With<Player>>,
     mut camera: Query<
         &mut Transform,
         (
@@ -52,54 +65,98 @@ pub(crate) fn move_camera(
         return;
     }
     if player.is_empty() {
-        error!("Found no player...?");
-        return;
-    }
-    if pt_query.is_empty() {
         error!("Found no player position...?");
         return;
     }
 
-    // let pt = player.single().1;
-    let mut pr = player.single_mut();
-    let ct = camera.single();
-    let pt = pt_query.single();
-
-    let x = if pt.translation.x > (ct.translation.x + WIN_W / 2.) {
-        ct.translation.x + WIN_W
-    } else if pt.translation.x < (ct.translation.x - WIN_W / 2.) {
-        ct.translation.x - WIN_W
-    } else {
-        ct.translation.x
+    let pt = player.single().translation.truncate();
+    let mut ct = camera.single_mut();
+    let current = ct.translation.truncate();
+
+    let target = match *mode {
+        // Centered on whichever screen the player is on
+        CameraMode::ScreenFlip => {
+            let (x, y) = rendering_to_logical(pt.x, pt.y);
+            Vec2::from(logical_to_rendering(x, y))
+        }
+        CameraMode::SmoothFollow => {
+            // Just far enough to put the player back on the edge of the dead zone
+            let from_center = pt - current;
+            let outside = from_center - from_center.clamp(-DEAD_ZONE, DEAD_ZONE);
+            // Closes the same share of the gap every second, whatever the frame rate
+            let eased = 1. - (-CAMERA_EASING * time.delta_seconds()).exp();
+            current.lerp(current + outside, eased)
+        }
     };
 
-    // Change the player's current chunk center if the camera has switched chunks
-    if pt.translation.x > (ct.translation.x + WIN_W / 2.) {
-        pr.current_chunk.0 += 1;
-    } else if pt.translation.x < (ct.translation.x - WIN_W / 2.) {
-        pr.current_chunk.0 -= 1;
-    }
+    *ct = Transform::from_xyz(target.x, target.y, CAMERA_Z_VALUE);
+}
 
-    if pt.translation.y > (ct.translation.y + WIN_H / 2. + TILE_SIZE) {
-        pr.current_chunk.1 += 1;
-    } else if pt.translation.y < (ct.translation.y - WIN_H / 2. - TILE_SIZE) {
-        pr.current_chunk.1 -= 1;
+/// Switch between the camera modes with F
+pub(crate) fn toggle_camera_mode(
+    input: Res<Input<KeyCode>>,
+    mut mode: ResMut<CameraMode>,
+    mut text_buffer: ResMut<TextBuffer>,
+) {
+    if !input.just_released(KeyCode::F) {
+        return;
     }
 
-    // I am not sure why the window top is slightly off balance
-    // and needing the +/- TILE_SIZE check, but it prevents halves of tiles
-    // compounding and resulting in mostly void screens.
-    let y = if pt.translation.y > (ct.translation.y + WIN_H / 2. + TILE_SIZE) {
-        ct.translation.y + WIN_H
-    } else if pt.translation.y < (ct.translation.y - WIN_H / 2. - TILE_SIZE) {
-        ct.translation.y - WIN_H
-    } else {
-        ct.translation.y
+    let (next, text) = match *mode {
+        CameraMode::ScreenFlip => (CameraMode::SmoothFollow, "The camera follows you around."),
+        CameraMode::SmoothFollow => (CameraMode::ScreenFlip, "The camera stays on your screen."),
     };
+    *mode = next;
+    text_buffer.bottom_text.push_back(PooledText {
+        text: text.to_string(),
+        pooled: false,
+    });
+}
 
-    // Move camera only when the player actually leaves a screen boundary
+/// Zoom every 2D camera to fit the window.
+///
+/// The overworld gets the biggest whole number zoom that still fills the window, so its
+/// pixel art stays sharp, and shows more of the world around the player if that leaves room.
+/// Menus and battles are drawn for `WIN_W` by `WIN_H` and get zoomed to fill the window,
+/// cutting off whatever sticks out on the longer side.
+pub(crate) fn fit_cameras(
+    windows: Res<Windows>,
+    state: Res<CurrentState<GameState>>,
+    mut cameras: Query<(&mut OrthographicProjection, Option<&MainCamera>), With<Camera2d>>,
+) {
+    let Some(window) = windows.get_primary() else {
+        return;
+    };
+    // In physical pixels, so whole number zooms line up with the pixels of the screen
+    let (width, height) = (
+        window.physical_width() as f32,
+        window.physical_height() as f32,
+    );
+    // Minimized
+    if width == 0. || height == 0. {
+        return;
+    }
 
-    for mut transform in camera.iter_mut() {
-        *transform = Transform::from_xyz(x, y, CAMERA_Z_VALUE);
+    let fill = (width / WIN_W).max(height / WIN_H);
+    for (mut projection, main) in cameras.iter_mut() {
+        let zoom = if main.is_some() && state.0 == GameState::Playing {
+            fill.floor().max(1.)
+        } else {
+            fill
+        };
+        // The projection works in logical pixels
+        let scale = window.scale_factor() as f32 / zoom;
+        if projection.scale != scale {
+            projection.scale = scale;
+        }
     }
 }
+
+/// Half the width and height of the world a camera shows
+pub(crate) fn half_view(projection: &OrthographicProjection) -> Vec2 {
+    let size = Vec2::new(
+        projection.right - projection.left,
+        projection.top - projection.bottom,
+    );
+    size * projection.scale / 2.
+}
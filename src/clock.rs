@@ -0,0 +1,136 @@
+use crate::camera::{half_view, MainCamera};
+use crate::world::{PooledText, TextBuffer};
+use crate::GameState;
+use bevy::prelude::*;
+use iyes_loopless::prelude::*;
+use std::env;
+use std::f32::consts::TAU;
+
+/// Environment variable that can be set to how many seconds a whole day and night takes
+pub(crate) const DAY_LENGTH_VAR: &str = "WASTE_DAY_LENGTH";
+/// Seconds a whole day and night takes, unless `DAY_LENGTH_VAR` says otherwise
+const DAY_LENGTH: f32 = 300.;
+/// Share of the day already gone when a game starts, so it starts in the morning
+const START_OF_DAY: f32 = 0.3;
+/// Daylight below which it counts as night
+const NIGHTFALL: f32 = 0.3;
+/// Color the overworld gets shaded with at midnight, as 8 bit RGBA
+const NIGHT_SHADE: [u8; 4] = [10, 15, 60, 150];
+/// Above the fog, under the backgrounds that battles and the pause screen draw over the overworld
+const NIGHT_SHADE_Z: f32 = -0.4;
+/// Levels monsters met at night have on top of the player's
+pub(crate) const NIGHT_LEVELS: usize = 1;
+
+/// Time of day in the overworld. It only runs while the player is out in it.
+pub(crate) struct WorldClock {
+    /// Seconds since midnight
+    pub(crate) time: f32,
+    /// Seconds a whole day and night takes
+    pub(crate) day_length: f32,
+}
+
+impl Default for WorldClock {
+    fn default() -> Self {
+        let day_length = env::var(DAY_LENGTH_VAR)
+            .ok()
+            .and_then(|s| s.trim().parse::<f32>().ok())
+            .filter(|length| *length > 0.)
+            .unwrap_or(DAY_LENGTH);
+        Self {
+            time: START_OF_DAY * day_length,
+            day_length,
+        }
+    }
+}
+
+impl WorldClock {
+    /// How light it is, from 0 at midnight to 1 at noon
+    pub(crate) fn daylight(&self) -> f32 {
+        (1. - (self.time / self.day_length * TAU).cos()) / 2.
+    }
+
+    pub(crate) fn is_night(&self) -> bool {
+        self.daylight() < NIGHTFALL
+    }
+}
+
+/// Darkens the overworld on screen the further it is from noon
+#[derive(Component)]
+pub(crate) struct NightShade;
+
+pub(crate) struct ClockPlugin;
+
+impl Plugin for ClockPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<WorldClock>()
+            .add_enter_system(GameState::StartPlaying, spawn_night_shade)
+            .add_system_set(
+                ConditionSet::new()
+                    .run_in_state(GameState::Playing)
+                    .with_system(tick_clock)
+                    .with_system(shade_night)
+                    .into(),
+            );
+    }
+}
+
+fn spawn_night_shade(mut commands: Commands) {
+    commands
+        .spawn_bundle(SpriteBundle {
+            sprite: Sprite {
+                color: Color::NONE,
+                ..default()
+            },
+            transform: Transform::from_xyz(0., 0., NIGHT_SHADE_Z),
+            ..default()
+        })
+        .insert(NightShade);
+}
+
+/// Move the clock along, and tell the player when night falls or the sun comes up
+fn tick_clock(time: Res<Time>, mut clock: ResMut<WorldClock>, mut text_buffer: ResMut<TextBuffer>) {
+    let was_night = clock.is_night();
+    clock.time = (clock.time + time.delta_seconds()) % clock.day_length;
+    if clock.is_night() == was_night {
+        return;
+    }
+
+    let text = if clock.is_night() {
+        "Night falls over the Wastes. Monsters grow bolder."
+    } else {
+        "The sun comes up over the Wastes."
+    };
+    text_buffer.bottom_text.push_back(PooledText {
+        text: text.to_string(),
+        pooled: false,
+    });
+}
+
+type Shade<'w, 's> = Query<
+    'w,
+    's,
+    (&'static mut Transform, &'static mut Sprite),
+    (With<NightShade>, Without<MainCamera>),
+>;
+
+/// Cover what the camera sees with the shade for the time of day
+fn shade_night(
+    clock: Res<WorldClock>,
+    camera: Query<(&Transform, &OrthographicProjection), With<MainCamera>>,
+    mut shade: Shade,
+) {
+    let (Ok((ct, projection)), Ok((mut transform, mut sprite))) =
+        (camera.get_single(), shade.get_single_mut())
+    else {
+        return;
+    };
+
+    transform.translation = ct.translation.truncate().extend(NIGHT_SHADE_Z);
+    // A few views across, so it still covers the screen on a frame where the camera
+    // flipped to the next screen before this caught up with it
+    sprite.custom_size = Some(half_view(projection) * 8.);
+    // Squared, so days stay clear for longer before it gets dark
+    let darkness = (1. - clock.daylight()).powi(2);
+    let [r, g, b, a] = NIGHT_SHADE;
+    sprite.color = Color::rgba_u8(r, g, b, (a as f32 * darkness) as u8);
+}
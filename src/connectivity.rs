@@ -0,0 +1,279 @@
+use crate::tiles::TileRegistry;
+use crate::wfc::{ChunkEdges, MAP_HEIGHT, MAP_WIDTH};
+use std::collections::{HashMap, HashSet, VecDeque};
+
+/// (row, column) of a cell on the border of a chunk, and the tile across the border from it
+type BorderCell = ((usize, usize), Option<usize>);
+
+/// Which parts of a chunk the player can't get to from the rest of it.
+///
+/// The biggest region of walkable tiles counts as the chunk's main region,
+/// everything else has to connect to it.
+#[derive(Debug, Clone, Default)]
+pub struct Reachability {
+    /// Cells of the main region
+    main: HashSet<(usize, usize)>,
+    /// Walkable regions that can't be reached from the main region
+    pub pockets: Vec<Vec<(usize, usize)>>,
+    /// For each side without a way across into the main region, the border cells a crossing
+    /// could go through. Sides whose neighbor is solid all along can never be crossed,
+    /// so they're left out.
+    pub blocked: Vec<Vec<(usize, usize)>>,
+}
+
+impl Reachability {
+    /// Check if every walkable tile is reachable and every side can be crossed
+    pub fn is_connected(&self) -> bool {
+        self.pockets.is_empty() && self.blocked.is_empty()
+    }
+}
+
+/// Flood fill the walkable tiles of a chunk and check them against the neighbors in `edges`.
+///
+/// Sides without a neighbor yet still need a walkable border tile,
+/// so the chunk generated there later has something to line up with.
+pub fn reachability(
+    tiles: &[Vec<usize>],
+    edges: &ChunkEdges,
+    registry: &TileRegistry,
+) -> Reachability {
+    let mut regions = regions(tiles, registry);
+    if regions.is_empty() {
+        return Reachability::default();
+    }
+    let main: HashSet<(usize, usize)> = regions.remove(0).into_iter().collect();
+
+    let blocked = sides(edges)
+        .into_iter()
+        .filter_map(|side| {
+            let crossable = side
+                .into_iter()
+                .filter(|(_, across)| across.is_none_or(|t| registry.walkable(t)))
+                .map(|(cell, _)| cell)
+                .collect::<Vec<(usize, usize)>>();
+            let crossed = crossable.iter().any(|cell| main.contains(cell));
+            (!crossed && !crossable.is_empty()).then_some(crossable)
+        })
+        .collect();
+
+    Reachability {
+        main,
+        pockets: regions,
+        blocked,
+    }
+}
+
+/// Cut paths through solid tiles until everything in `reachability()` is connected,
+/// turning the fewest tiles possible into `floor`. Cells in `fixed` are never changed.
+///
+/// Returns the number of tiles that were changed.
+pub fn connect(
+    tiles: &mut [Vec<usize>],
+    edges: &ChunkEdges,
+    registry: &TileRegistry,
+    fixed: &HashSet<(usize, usize)>,
+    floor: usize,
+) -> usize {
+    let mut carved = 0;
+    // Every path cuts through at least one solid tile, so this runs out eventually
+    'connect: loop {
+        let reach = reachability(tiles, edges, registry);
+        for sources in reach.pockets.iter().chain(&reach.blocked) {
+            if let Some(path) = cheapest_path(tiles, registry, fixed, sources, &reach.main) {
+                for (row, col) in path {
+                    if !registry.walkable(tiles[row][col]) {
+                        tiles[row][col] = floor;
+                        carved += 1;
+                    }
+                }
+                continue 'connect;
+            }
+        }
+        return carved;
+    }
+}
+
+/// Connected regions of walkable tiles, biggest first
+fn regions(tiles: &[Vec<usize>], registry: &TileRegistry) -> Vec<Vec<(usize, usize)>> {
+    let mut seen: HashSet<(usize, usize)> = HashSet::new();
+    let mut regions = Vec::new();
+    for row in 0..MAP_HEIGHT {
+        for col in 0..MAP_WIDTH {
+            if !registry.walkable(tiles[row][col]) || !seen.insert((row, col)) {
+                continue;
+            }
+            let mut region = vec![(row, col)];
+            let mut queue = VecDeque::from([(row, col)]);
+            while let Some(cell) = queue.pop_front() {
+                for next in neighbors(cell) {
+                    if registry.walkable(tiles[next.0][next.1]) && seen.insert(next) {
+                        region.push(next);
+                        queue.push_back(next);
+                    }
+                }
+            }
+            regions.push(region);
+        }
+    }
+    // Stable, so the first region found wins a tie
+    regions.sort_by_key(|region| std::cmp::Reverse(region.len()));
+    regions
+}
+
+/// Path from any of `sources` to any of `targets` going through the fewest solid tiles
+fn cheapest_path(
+    tiles: &[Vec<usize>],
+    registry: &TileRegistry,
+    fixed: &HashSet<(usize, usize)>,
+    sources: &[(usize, usize)],
+    targets: &HashSet<(usize, usize)>,
+) -> Option<Vec<(usize, usize)>> {
+    // Walking over a walkable tile is free, cutting through a solid one costs one
+    let cost = |(row, col): (usize, usize)| {
+        if registry.walkable(tiles[row][col]) {
+            Some(0)
+        } else if fixed.contains(&(row, col)) {
+            None
+        } else {
+            Some(1)
+        }
+    };
+
+    let mut costs: HashMap<(usize, usize), usize> = HashMap::new();
+    let mut came_from: HashMap<(usize, usize), (usize, usize)> = HashMap::new();
+    let mut queue = VecDeque::new();
+    for &source in sources {
+        if let Some(c) = cost(source) {
+            costs.insert(source, c);
+            match c {
+                0 => queue.push_front(source),
+                _ => queue.push_back(source),
+            }
+        }
+    }
+
+    // Free steps go to the front of the queue, so cells come out cheapest first
+    while let Some(cell) = queue.pop_front() {
+        if targets.contains(&cell) {
+            let mut path = vec![cell];
+            while let Some(previous) = came_from.get(path.last().unwrap()) {
+                path.push(*previous);
+            }
+            return Some(path);
+        }
+        for next in neighbors(cell) {
+            let Some(c) = cost(next) else {
+                continue;
+            };
+            let total = costs[&cell] + c;
+            if costs.get(&next).is_none_or(|&old| total < old) {
+                costs.insert(next, total);
+                came_from.insert(next, cell);
+                match c {
+                    0 => queue.push_front(next),
+                    _ => queue.push_back(next),
+                }
+            }
+        }
+    }
+    None
+}
+
+/// Cells sharing a side with (row, col) that are inside the chunk
+fn neighbors((row, col): (usize, usize)) -> impl Iterator<Item = (usize, usize)> {
+    [
+        (row.wrapping_sub(1), col),
+        (row + 1, col),
+        (row, col.wrapping_sub(1)),
+        (row, col + 1),
+    ]
+    .into_iter()
+    .filter(|&(row, col)| row < MAP_HEIGHT && col < MAP_WIDTH)
+}
+
+/// Border cells of each side in `Dir::ALL` order, each with the tile across the border
+/// if the neighbor there has been generated
+fn sides(edges: &ChunkEdges) -> [Vec<BorderCell>; 4] {
+    let across = |edge: &Option<Vec<usize>>, i: usize| edge.as_ref().map(|tiles| tiles[i]);
+    [
+        (0..MAP_HEIGHT)
+            .map(|row| ((row, 0), across(&edges.west, row)))
+            .collect(),
+        (0..MAP_WIDTH)
+            .map(|col| ((0, col), across(&edges.north, col)))
+            .collect(),
+        (0..MAP_HEIGHT)
+            .map(|row| ((row, MAP_WIDTH - 1), across(&edges.east, row)))
+            .collect(),
+        (0..MAP_WIDTH)
+            .map(|col| ((MAP_HEIGHT - 1, col), across(&edges.south, col)))
+            .collect(),
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FLOOR: usize = 0;
+    const WALL: usize = 1;
+
+    fn registry() -> TileRegistry {
+        TileRegistry::parse(
+            "sheet tiles.png 2 1\n0 floor walkable none 100 -\n1 wall solid none 100 -\n",
+        )
+        .unwrap()
+    }
+
+    /// Walls with a corridor across the middle from west to east, and one floor tile
+    /// walled in near the top left corner
+    fn corridor() -> Vec<Vec<usize>> {
+        let mut tiles = vec![vec![WALL; MAP_WIDTH]; MAP_HEIGHT];
+        tiles[5] = vec![FLOOR; MAP_WIDTH];
+        tiles[1][1] = FLOOR;
+        tiles
+    }
+
+    #[test]
+    fn finds_pockets_and_sides_that_cannot_be_crossed() {
+        let registry = registry();
+        let reach = reachability(&corridor(), &ChunkEdges::default(), &registry);
+        assert_eq!(reach.pockets, vec![vec![(1, 1)]]);
+        // North and south have nobody there yet, so any of their cells would do
+        assert_eq!(reach.blocked.len(), 2);
+        assert!(!reach.is_connected());
+
+        // A neighbor that is solid all along can't be walked into anyway
+        let edges = ChunkEdges {
+            north: Some(vec![WALL; MAP_WIDTH]),
+            south: Some(vec![WALL; MAP_WIDTH]),
+            ..Default::default()
+        };
+        let reach = reachability(&corridor(), &edges, &registry);
+        assert!(reach.blocked.is_empty());
+    }
+
+    #[test]
+    fn connect_cuts_the_shortest_paths_around_fixed_cells() {
+        let registry = registry();
+        let mut tiles = corridor();
+        // Only the last cell of the south neighbor's border can be walked on
+        let mut south = vec![WALL; MAP_WIDTH];
+        south[MAP_WIDTH - 1] = FLOOR;
+        let edges = ChunkEdges {
+            north: Some(vec![WALL; MAP_WIDTH]),
+            south: Some(south),
+            ..Default::default()
+        };
+        // The straight way down from the pocket is off limits
+        let fixed = HashSet::from([(2, 1), (3, 1), (4, 1)]);
+
+        let carved = connect(&mut tiles, &edges, &registry, &fixed, FLOOR);
+
+        // Four tiles around the fixed ones, and six down to the walkable cell across the south
+        assert_eq!(carved, 10);
+        assert!(fixed.iter().all(|&(row, col)| tiles[row][col] == WALL));
+        assert!((6..MAP_HEIGHT).all(|row| tiles[row][MAP_WIDTH - 1] == FLOOR));
+        assert!(reachability(&tiles, &edges, &registry).is_connected());
+    }
+}
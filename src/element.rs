@@ -0,0 +1,36 @@
+use rand::distributions::{Distribution, Standard};
+use serde::{Deserialize, Serialize};
+
+// Elemental types
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+// Monsters carry it as a component in the game, world generation only needs the enum
+#[cfg_attr(feature = "game", derive(bevy::prelude::Component))]
+pub enum Element {
+    Scav,
+    Growth,
+    Ember,
+    Flood,
+    Rad,
+    Robot,
+    Clean,
+    Filth,
+}
+
+// elements enum to be able to pick randomly which type we want
+impl Distribution<Element> for Standard {
+    /// Randomly sample the element enum
+    fn sample<R: rand::Rng + ?Sized>(&self, rng: &mut R) -> Element {
+        // Randomly generate a number from 0 to 7 then return an enum variant
+        // corresponding to that.
+        match rng.gen_range(0..=7) {
+            0 => Element::Scav,
+            1 => Element::Growth,
+            2 => Element::Ember,
+            3 => Element::Flood,
+            4 => Element::Rad,
+            5 => Element::Robot,
+            6 => Element::Clean,
+            _ => Element::Filth,
+        }
+    }
+}
@@ -1,5 +1,6 @@
 use crate::backgrounds::Tile;
 use crate::camera::HelpCamera;
+use crate::layout::{vh, vw};
 use crate::player::Player;
 use crate::GameState;
 use bevy::prelude::*;
@@ -72,8 +73,8 @@ pub(crate) fn setup_help(
         .insert(Style {
             position_type: PositionType::Absolute,
             position: UiRect {
-                bottom: Val::Px(700.0),
-                left: Val::Px(600.0),
+                bottom: vh(700.0),
+                left: vw(600.0),
                 ..default()
             },
             ..default()
@@ -92,8 +93,8 @@ pub(crate) fn setup_help(
         .insert(Style {
             position_type: PositionType::Absolute,
             position: UiRect {
-                bottom: Val::Px(600.0),
-                left: Val::Px(500.0),
+                bottom: vh(600.0),
+                left: vw(500.0),
                 ..default()
             },
             ..default()
@@ -102,7 +103,7 @@ pub(crate) fn setup_help(
 
     commands
         .spawn_bundle(TextBundle::from_section(
-            "W: move up, S: move down, A: move left, \nD: move right, P: party size, G: game progress, \nI: inventory, Esc: pause, Q: quit",
+            "W: move up, S: move down, A: move left, \nD: move right, P: party size, G: game progress, \nI: inventory, M: map, F: camera, Esc: pause, Q: quit",
             TextStyle {
                 font: asset_server.load("buttons/PressStart2P.ttf"),
                 font_size: 25.0,
@@ -112,8 +113,8 @@ pub(crate) fn setup_help(
         .insert(Style {
             position_type: PositionType::Absolute,
             position: UiRect {
-                bottom: Val::Px(500.0),
-                left: Val::Px(10.0),
+                bottom: vh(500.0),
+                left: vw(10.0),
                 ..default()
             },
             ..default()
@@ -132,8 +133,8 @@ pub(crate) fn setup_help(
         .insert(Style {
             position_type: PositionType::Absolute,
             position: UiRect {
-                bottom: Val::Px(400.0),
-                left: Val::Px(500.0),
+                bottom: vh(400.0),
+                left: vw(500.0),
                 ..default()
             },
             ..default()
@@ -152,8 +153,8 @@ pub(crate) fn setup_help(
         .insert(Style {
             position_type: PositionType::Absolute,
             position: UiRect {
-                bottom: Val::Px(325.0),
-                left: Val::Px(10.0),
+                bottom: vh(325.0),
+                left: vw(10.0),
                 ..default()
             },
             ..default()
@@ -172,8 +173,8 @@ pub(crate) fn setup_help(
         .insert(Style {
             position_type: PositionType::Absolute,
             position: UiRect {
-                bottom: Val::Px(200.0),
-                left: Val::Px(500.0),
+                bottom: vh(200.0),
+                left: vw(500.0),
                 ..default()
             },
             ..default()
@@ -192,8 +193,8 @@ pub(crate) fn setup_help(
         .insert(Style {
             position_type: PositionType::Absolute,
             position: UiRect {
-                bottom: Val::Px(150.0),
-                left: Val::Px(10.0),
+                bottom: vh(150.0),
+                left: vw(10.0),
                 ..default()
             },
             ..default()
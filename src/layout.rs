@@ -0,0 +1,73 @@
+use crate::backgrounds::{WIN_H, WIN_W};
+use bevy::prelude::*;
+
+/// How much bigger than `WIN_W` by `WIN_H` the window is, going by the side it fills first.
+/// The UI was laid out at that size, so text and images get scaled by this to keep up.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct LayoutScale(pub(crate) f32);
+
+impl Default for LayoutScale {
+    fn default() -> Self {
+        Self(1.)
+    }
+}
+
+/// Font sizes a text was spawned with, one per section, before any scaling
+#[derive(Component)]
+pub(crate) struct BaseFontSizes(Vec<f32>);
+
+pub(crate) struct LayoutPlugin;
+
+impl Plugin for LayoutPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<LayoutScale>()
+            .add_system(update_layout_scale)
+            .add_system(scale_text.after(update_layout_scale));
+    }
+}
+
+/// Horizontal offset or size of `px` pixels at the window size the UI was laid out at,
+/// as a share of the window's width
+pub(crate) fn vw(px: f32) -> Val {
+    Val::Percent(px / WIN_W * 100.)
+}
+
+/// Same as `vw()`, but for vertical offsets and sizes
+pub(crate) fn vh(px: f32) -> Val {
+    Val::Percent(px / WIN_H * 100.)
+}
+
+fn update_layout_scale(windows: Res<Windows>, mut layout_scale: ResMut<LayoutScale>) {
+    let Some(window) = windows.get_primary() else {
+        return;
+    };
+    let scale = (window.width() / WIN_W).min(window.height() / WIN_H);
+    // Only when it actually changed, so texts don't all get rescaled every frame
+    if scale > 0. && scale != layout_scale.0 {
+        layout_scale.0 = scale;
+    }
+}
+
+/// Scale every text by `LayoutScale`, new ones as they show up and the rest when it changes
+fn scale_text(
+    mut commands: Commands,
+    layout_scale: Res<LayoutScale>,
+    mut new: Query<(Entity, &mut Text), Without<BaseFontSizes>>,
+    mut scaled: Query<(&mut Text, &BaseFontSizes)>,
+) {
+    for (entity, mut text) in new.iter_mut() {
+        let sizes = text.sections.iter().map(|s| s.style.font_size).collect();
+        for section in text.sections.iter_mut() {
+            section.style.font_size *= layout_scale.0;
+        }
+        commands.entity(entity).insert(BaseFontSizes(sizes));
+    }
+
+    if layout_scale.is_changed() {
+        for (mut text, base) in scaled.iter_mut() {
+            for (section, size) in text.sections.iter_mut().zip(&base.0) {
+                section.style.font_size = size * layout_scale.0;
+            }
+        }
+    }
+}
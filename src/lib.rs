@@ -0,0 +1,22 @@
+// World generation, shared by the game and the `worldgen` tool.
+//
+// Nothing in here needs Bevy, so the tool builds without it. Problems are logged through the
+// `log` crate, which Bevy's log picks up in the game.
+#![warn(unused)]
+#![deny(unsafe_code)]
+#![deny(unreachable_code)]
+#![deny(while_true)]
+// Deny only by clippy
+#![deny(clippy::empty_loop)]
+#![deny(clippy::while_immutable_condition)]
+#![deny(clippy::self_assignment)]
+
+pub mod biomes;
+pub mod connectivity;
+pub mod element;
+pub mod prefabs;
+pub mod seams;
+pub mod specials;
+pub mod tiles;
+pub mod wfc;
+pub mod wfc_input;
@@ -25,6 +25,7 @@ pub(crate) enum GameState {
     Battle,
     Credits,
     Help,
+    Map,
     MultiplayerMenu,
     MultiplayerWaiting,
     MultiplayerPvPBattle,
@@ -39,9 +40,12 @@ pub(crate) const TITLE: &str = "Waste";
 mod backgrounds;
 mod battle;
 mod camera;
+mod clock;
 mod credits;
 mod game_client;
 mod help;
+mod layout;
+mod map;
 mod monster;
 mod multiplayer_menu;
 mod multiplayer_pve;
@@ -52,16 +56,18 @@ mod pause;
 mod player;
 mod quests;
 mod start_menu;
-mod wfc;
 mod world;
 
 //use statements:
 use backgrounds::*;
 use battle::*;
 use camera::*;
+use clock::*;
 use credits::*;
 use game_client::*;
 use help::*;
+use layout::*;
+use map::*;
 use monster::*;
 use multiplayer_menu::*;
 use multiplayer_pve::*;
@@ -72,7 +78,7 @@ use pause::*;
 use player::*;
 use quests::*;
 use start_menu::*;
-use wfc::*;
+use waste::wfc::ProcGen;
 use world::*;
 
 // END CUSTOM MODULES
@@ -95,6 +101,8 @@ fn main() {
             ..default()
         })
         .init_resource::<WorldMap>()
+        .init_resource::<ChunkPrefetch>()
+        .init_resource::<CameraMode>()
         .init_resource::<GameProgress>()
         .init_resource::<TypeSystem>()
         .init_resource::<ProcGen>()
@@ -108,11 +116,15 @@ fn main() {
         .add_plugin(CreditsPlugin)
         .add_plugin(HelpPlugin)
         .add_plugin(PausePlugin)
+        .add_plugin(MapPlugin)
+        .add_plugin(ClockPlugin)
         .add_plugin(BattlePlugin)
         .add_plugin(MultMenuPlugin)
         .add_plugin(MultiplayerWaitingPlugin)
         .add_plugin(MultPvPPlugin)
         .add_plugin(MultPvEPlugin)
+        .add_plugin(LayoutPlugin)
+        .add_system(fit_cameras)
         .add_enter_system_set(
             GameState::StartPlaying,
             // This system set is unconditional, as it is being added in an enter helper
@@ -127,8 +139,14 @@ fn main() {
                 .run_in_state(GameState::Playing)
                 .with_system(move_player)
                 .with_system(move_camera)
+                .with_system(toggle_camera_mode)
                 .with_system(animate_sprite)
+                .with_system(animate_tiles)
                 .with_system(expand_map)
+                .with_system(finish_chunks)
+                .with_system(stream_chunks)
+                .with_system(lift_fog)
+                .with_system(sort_depth)
                 .with_system(win_game)
                 .with_system(handle_pause)
                 .into(),
@@ -179,9 +197,14 @@ pub(crate) fn setup_game(
         // Was considering giving player marker struct an xyz component
         // til I realized transform handles that for us.
         .insert(AnimationTimer(Timer::from_seconds(ANIM_TIME, true)))
+        // Sorted by the bottom of the hitbox, the same edge that bumps into things
+        .insert(DepthSorted {
+            base: PLAYER_HITBOX / 2.,
+        })
         //player stats init here:
         .insert(Player {
             current_chunk: (0, 0),
+            heading: Vec2::ZERO,
             //constants can be found in player.rs,
         });
 
@@ -202,6 +225,9 @@ pub(crate) fn setup_game(
     commands.insert_resource(NextState(GameState::Playing));
 }
 
+/// Entities that only exist on top of the world: fog, the night shade and chunks still generating
+type Overlays<'w, 's> = Query<'w, 's, Entity, Or<(With<Fog>, With<NightShade>, With<ChunkTask>)>>;
+
 /// Tear down ALL significant resources for the game, and despawn all relevant
 /// in game entities. This should be used when bailing out of the credits state
 /// after beating the game, or when exiting multiplayer to move to singleplayer.*
@@ -211,6 +237,7 @@ pub(crate) fn teardown(
     mut commands: Commands,
     camera_query: Query<Entity, With<MainCamera>>,
     background_query: Query<Entity, With<Tile>>,
+    overlay_query: Overlays,
     player_query: Query<Entity, With<Player>>,
     monster_query: Query<Entity, With<PartyMonster>>,
     npc_query: Query<Entity, With<NPC>>,
@@ -225,6 +252,11 @@ pub(crate) fn teardown(
         commands.entity(background).despawn();
     });
 
+    // Despawn fog, the night shade and chunks still generating for the old world
+    overlay_query.for_each(|overlay| {
+        commands.entity(overlay).despawn();
+    });
+
     // Despawn player
     player_query.for_each(|player| {
         commands.entity(player).despawn();
@@ -247,9 +279,15 @@ pub(crate) fn teardown(
     commands.remove_resource::<WorldMap>();
     // Remove the game progress resource
     commands.remove_resource::<GameProgress>();
+    // Remove the procgen resource so the next world gets a fresh seed
+    commands.remove_resource::<ProcGen>();
+    // Remove the clock so the next game starts in the morning
+    commands.remove_resource::<WorldClock>();
     // Re-initialize the resources
     commands.init_resource::<WorldMap>();
     commands.init_resource::<GameProgress>();
+    commands.init_resource::<ProcGen>();
+    commands.init_resource::<WorldClock>();
 }
 
 /// Mark that game has been completed and transition to credits.
@@ -303,8 +341,8 @@ pub fn display_text(
                     align_self: AlignSelf::FlexEnd,
                     position_type: PositionType::Absolute,
                     position: UiRect {
-                        top: Val::Px(display_latest - i as f32 * 30.),
-                        left: Val::Px(10.0),
+                        top: vh(display_latest - i as f32 * 30.),
+                        left: vw(10.0),
                         ..default()
                     },
                     ..default()
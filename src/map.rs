@@ -0,0 +1,616 @@
+use crate::backgrounds::{Chunk, MapAtlas, TilePosition, TILE_SIZE, WIN_H, WIN_W};
+use crate::layout::{vh, vw, LayoutScale};
+use crate::player::Player;
+use crate::quests::NPC;
+use crate::world::{rendering_to_tile, WorldMap};
+use crate::GameState;
+use bevy::ecs::system::SystemParam;
+use bevy::prelude::*;
+use bevy::render::render_resource::{Extent3d, TextureDimension, TextureFormat};
+use iyes_loopless::prelude::*;
+use waste::tiles::{TileInteraction, TileRegistry};
+use waste::wfc::{ProcGen, MAP_HEIGHT, MAP_WIDTH};
+
+/// Pixels per tile on the corner minimap
+const MINIMAP_SCALE: usize = 4;
+/// Rings of chunks around the player's chunk the minimap shows
+const MINIMAP_RADIUS: isize = 1;
+/// Gap between the minimap and the top right corner of the window, at the window size
+/// the UI is laid out for
+const MINIMAP_MARGIN: f32 = 10.;
+/// Most pixels per tile on the map screen, so a small discovered area doesn't get blown up
+const MAP_MAX_SCALE: usize = 8;
+/// Room left above the map screen's picture for its title
+const MAP_TOP: f32 = 70.;
+/// Room left below the map screen's picture for its legend
+const MAP_BOTTOM: f32 = 110.;
+
+// Map colors, as 8 bit RGBA
+const UNEXPLORED: [u8; 4] = [20, 20, 20, 200];
+/// Tiles whose color couldn't be read from the tilesheet
+const UNKNOWN: [u8; 4] = [128, 128, 128, 255];
+const OUTLINE: [u8; 4] = [0, 0, 0, 255];
+const PLAYER_MARKER: [u8; 4] = [255, 255, 255, 255];
+const HEAL_MARKER: [u8; 4] = [235, 50, 80, 255];
+const QUEST_MARKER: [u8; 4] = [250, 210, 40, 255];
+const LANDMARK_MARKER: [u8; 4] = [180, 90, 240, 255];
+
+pub(crate) struct MapPlugin;
+
+#[derive(Component)]
+pub(crate) struct MinimapUIElement;
+
+#[derive(Component)]
+pub(crate) struct MapUIElement;
+
+/// Picture the corner minimap shows, redrawn whenever the player steps onto another tile
+/// or sees more of the chunks around them
+pub(crate) struct Minimap {
+    image: Handle<Image>,
+    /// Average color of each tile in the overworld tilesheet, empty until the sheet has loaded
+    colors: Vec<[u8; 4]>,
+    /// Tile the player was on, and how many tiles in view had been seen,
+    /// when the minimap was last drawn
+    drawn_at: Option<(TilePosition, usize)>,
+}
+
+/// What both the minimap and the map screen get drawn from
+#[derive(SystemParam)]
+pub(crate) struct MapSources<'w, 's> {
+    world: Res<'w, WorldMap>,
+    procgen: Res<'w, ProcGen>,
+    player: Query<'w, 's, &'static Transform, With<Player>>,
+    /// NPCs walking around on their own rather than standing on a tile
+    npcs: Query<'w, 's, &'static Transform, (With<NPC>, Without<TilePosition>)>,
+}
+
+impl FromWorld for Minimap {
+    fn from_world(world: &mut World) -> Self {
+        let image = world.resource_mut::<Assets<Image>>().add(blank_image());
+        Self {
+            image,
+            colors: Vec::new(),
+            drawn_at: None,
+        }
+    }
+}
+
+impl Plugin for MapPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<Minimap>()
+            .add_enter_system(GameState::Playing, spawn_minimap)
+            .add_system_set(
+                ConditionSet::new()
+                    .run_in_state(GameState::Playing)
+                    .with_system(update_minimap)
+                    .with_system(resize_minimap)
+                    .with_system(handle_open_map)
+                    .into(),
+            )
+            .add_exit_system(GameState::Playing, despawn_minimap)
+            .add_enter_system(GameState::Map, setup_map)
+            .add_system_set(
+                ConditionSet::new()
+                    .run_in_state(GameState::Map)
+                    .with_system(handle_exit_map)
+                    .into(),
+            )
+            .add_exit_system(GameState::Map, despawn_map);
+    }
+}
+
+/// Which chunks a map shows, and how big
+#[derive(Debug, Clone, Copy)]
+struct MapView {
+    /// Logical position of the bottom left chunk
+    min: (isize, isize),
+    /// Logical position of the top right chunk
+    max: (isize, isize),
+    /// Width and height of a tile, in pixels
+    scale: usize,
+}
+
+impl MapView {
+    /// Every chunk within `radius` rings of `center`, diagonals included
+    fn around(center: (isize, isize), radius: isize, scale: usize) -> Self {
+        Self {
+            min: (center.0 - radius, center.1 - radius),
+            max: (center.0 + radius, center.1 + radius),
+            scale,
+        }
+    }
+
+    /// Smallest view with every discovered chunk in it, as big as it can be while fitting in
+    /// `width` by `height` pixels
+    fn discovered(world: &WorldMap, width: f32, height: f32) -> Self {
+        let positions: Vec<(isize, isize)> = discovered(world).map(|c| c.position).collect();
+        let xs = positions.iter().map(|&(x, _)| x);
+        let ys = positions.iter().map(|&(_, y)| y);
+        let min = (xs.clone().min().unwrap_or(0), ys.clone().min().unwrap_or(0));
+        let max = (xs.max().unwrap_or(0), ys.max().unwrap_or(0));
+        let tiles_wide = (max.0 - min.0 + 1) as usize * MAP_WIDTH;
+        let tiles_high = (max.1 - min.1 + 1) as usize * MAP_HEIGHT;
+        let scale = (width as usize / tiles_wide)
+            .min(height as usize / tiles_high)
+            .clamp(1, MAP_MAX_SCALE);
+        Self { min, max, scale }
+    }
+
+    /// Width and height of the picture, in pixels
+    fn size(&self) -> (usize, usize) {
+        (
+            (self.max.0 - self.min.0 + 1) as usize * MAP_WIDTH * self.scale,
+            (self.max.1 - self.min.1 + 1) as usize * MAP_HEIGHT * self.scale,
+        )
+    }
+
+    /// Check if the chunk at logical position (x, y) is in view
+    fn contains(&self, (x, y): (isize, isize)) -> bool {
+        (self.min.0..=self.max.0).contains(&x) && (self.min.1..=self.max.1).contains(&y)
+    }
+
+    /// Top left pixel of a tile, `None` if its chunk is out of view
+    fn corner(&self, position: &TilePosition) -> Option<(usize, usize)> {
+        if !self.contains(position.chunk) {
+            return None;
+        }
+        let (x, y) = position.chunk;
+        // Logical y goes up, but pictures go top to bottom
+        Some((
+            ((x - self.min.0) as usize * MAP_WIDTH + position.col) * self.scale,
+            ((self.max.1 - y) as usize * MAP_HEIGHT + position.row) * self.scale,
+        ))
+    }
+
+    /// Draw the tiles in view that the player has seen into `image`, resizing it to fit,
+    /// and mark the player, healing tiles, quest givers and landmarks on top.
+    /// `quest_givers` are NPCs that don't stand on a tile of their own.
+    fn draw(
+        &self,
+        image: &mut Image,
+        world: &WorldMap,
+        registry: &TileRegistry,
+        colors: &[[u8; 4]],
+        player: &TilePosition,
+        quest_givers: &[TilePosition],
+    ) {
+        let (width, height) = self.size();
+        let size = Extent3d {
+            width: width as u32,
+            height: height as u32,
+            depth_or_array_layers: 1,
+        };
+        if image.texture_descriptor.size != size {
+            image.resize(size);
+        }
+        for pixel in image.data.chunks_exact_mut(4) {
+            pixel.copy_from_slice(&UNEXPLORED);
+        }
+
+        let scale = self.scale;
+        // Markers go on after all the terrain, so the next tile over can't paint over them
+        let mut markers = Vec::new();
+        for chunk in discovered(world) {
+            let Some(top_left) = self.corner(&TilePosition {
+                chunk: chunk.position,
+                row: 0,
+                col: 0,
+            }) else {
+                continue;
+            };
+
+            for (row, line) in chunk.tiles.iter().enumerate() {
+                for (col, &tile) in line.iter().enumerate() {
+                    if !chunk.seen.contains(&(row, col)) {
+                        continue;
+                    }
+                    let corner = (top_left.0 + col * scale, top_left.1 + row * scale);
+                    let color = colors.get(tile).copied().unwrap_or(UNKNOWN);
+                    fill(image, corner, (scale, scale), color);
+                    if chunk.used_specials.contains(&(row, col)) {
+                        continue;
+                    }
+                    match registry.interaction(tile) {
+                        TileInteraction::Heal => markers.push((corner, HEAL_MARKER)),
+                        TileInteraction::Npc => markers.push((corner, QUEST_MARKER)),
+                        _ => {}
+                    }
+                }
+            }
+
+            if chunk.landmark.is_some() {
+                let (w, h) = (MAP_WIDTH * scale, MAP_HEIGHT * scale);
+                let line = (scale / 2).max(1);
+                fill(image, top_left, (w, line), LANDMARK_MARKER);
+                fill(image, top_left, (line, h), LANDMARK_MARKER);
+                fill(
+                    image,
+                    (top_left.0, top_left.1 + h - line),
+                    (w, line),
+                    LANDMARK_MARKER,
+                );
+                fill(
+                    image,
+                    (top_left.0 + w - line, top_left.1),
+                    (line, h),
+                    LANDMARK_MARKER,
+                );
+            }
+        }
+
+        for npc in quest_givers {
+            let seen = world
+                .chunk_ids
+                .get(&npc.chunk)
+                .and_then(|id| world.chunk_components.get(id))
+                .is_some_and(|chunk| chunk.seen.contains(&(npc.row, npc.col)));
+            if let Some(corner) = self.corner(npc).filter(|_| seen) {
+                markers.push((corner, QUEST_MARKER));
+            }
+        }
+        for (corner, color) in markers {
+            mark(image, corner, scale, color);
+        }
+
+        // The player is twice as big as everything else, centered on their tile
+        if let Some((x, y)) = self.corner(player) {
+            let corner = (x.saturating_sub(scale / 2), y.saturating_sub(scale / 2));
+            mark(image, corner, scale * 2, PLAYER_MARKER);
+        }
+    }
+}
+
+/// Chunks the player has seen at least one tile of
+fn discovered(world: &WorldMap) -> impl Iterator<Item = &Chunk> {
+    world
+        .chunk_components
+        .values()
+        .filter(|chunk| !chunk.seen.is_empty())
+}
+
+/// Fill a `size` rectangle with its top left corner at `corner`, cut off at the edges of `image`
+fn fill(image: &mut Image, corner: (usize, usize), size: (usize, usize), color: [u8; 4]) {
+    let width = image.texture_descriptor.size.width as usize;
+    let height = image.texture_descriptor.size.height as usize;
+    let (left, right) = (corner.0.min(width), (corner.0 + size.0).min(width));
+    for y in corner.1..(corner.1 + size.1).min(height) {
+        let row = y * width;
+        for pixel in image.data[(row + left) * 4..(row + right) * 4].chunks_exact_mut(4) {
+            pixel.copy_from_slice(&color);
+        }
+    }
+}
+
+/// Fill a `size` by `size` square with a one pixel outline around it, so it stands out
+fn mark(image: &mut Image, (x, y): (usize, usize), size: usize, color: [u8; 4]) {
+    let outline = (x.saturating_sub(1), y.saturating_sub(1));
+    fill(image, outline, (size + 2, size + 2), OUTLINE);
+    fill(image, (x, y), (size, size), color);
+}
+
+fn blank_image() -> Image {
+    Image::new_fill(
+        Extent3d {
+            width: 1,
+            height: 1,
+            depth_or_array_layers: 1,
+        },
+        TextureDimension::D2,
+        &UNEXPLORED,
+        TextureFormat::Rgba8UnormSrgb,
+    )
+}
+
+/// Average color of every tile in the tilesheet, leaving out transparent pixels
+fn tile_colors(sheet: &Image, registry: &TileRegistry) -> Vec<[u8; 4]> {
+    let count = registry.columns * registry.rows;
+    if sheet.texture_descriptor.format != TextureFormat::Rgba8UnormSrgb {
+        warn!(
+            "Tilesheet is {:?}, not 8 bit RGBA, so the map can't show tile colors",
+            sheet.texture_descriptor.format
+        );
+        return vec![UNKNOWN; count];
+    }
+
+    let width = sheet.texture_descriptor.size.width as usize;
+    let tile = TILE_SIZE as usize;
+    (0..count)
+        .map(|index| {
+            let left = (index % registry.columns) * tile;
+            let top = (index / registry.columns) * tile;
+            let mut sum = [0; 3];
+            let mut opaque = 0;
+            for y in top..top + tile {
+                for x in left..left + tile {
+                    let i = (y * width + x) * 4;
+                    match sheet.data.get(i..i + 4) {
+                        Some(pixel) if pixel[3] > 0 => {
+                            for (total, channel) in sum.iter_mut().zip(pixel) {
+                                *total += *channel as usize;
+                            }
+                            opaque += 1;
+                        }
+                        _ => {}
+                    }
+                }
+            }
+            match opaque {
+                0 => UNEXPLORED,
+                n => [
+                    (sum[0] / n) as u8,
+                    (sum[1] / n) as u8,
+                    (sum[2] / n) as u8,
+                    255,
+                ],
+            }
+        })
+        .collect()
+}
+
+/// Tiles that NPCs not drawn as part of a chunk are standing on
+fn wandering_quest_givers(
+    npcs: &Query<&Transform, (With<NPC>, Without<TilePosition>)>,
+) -> Vec<TilePosition> {
+    npcs.iter()
+        .map(|t| rendering_to_tile(t.translation.x, t.translation.y))
+        .collect()
+}
+
+/// Size of the minimap on screen, grown along with the rest of the UI
+fn minimap_size(layout_scale: &LayoutScale) -> Size<Val> {
+    let (width, height) = MapView::around((0, 0), MINIMAP_RADIUS, MINIMAP_SCALE).size();
+    Size::new(
+        Val::Px(width as f32 * layout_scale.0),
+        Val::Px(height as f32 * layout_scale.0),
+    )
+}
+
+pub(crate) fn spawn_minimap(
+    mut commands: Commands,
+    mut minimap: ResMut<Minimap>,
+    layout_scale: Res<LayoutScale>,
+) {
+    commands
+        .spawn_bundle(ImageBundle {
+            style: Style {
+                size: minimap_size(&layout_scale),
+                position_type: PositionType::Absolute,
+                position: UiRect {
+                    top: vh(MINIMAP_MARGIN),
+                    right: vw(MINIMAP_MARGIN),
+                    ..default()
+                },
+                ..default()
+            },
+            image: minimap.image.clone().into(),
+            ..default()
+        })
+        .insert(MinimapUIElement);
+    // The world may have changed since it was last drawn, even if the player hasn't moved
+    minimap.drawn_at = None;
+}
+
+/// Keep the minimap in proportion when the window gets resized
+pub(crate) fn resize_minimap(
+    layout_scale: Res<LayoutScale>,
+    mut minimaps: Query<&mut Style, With<MinimapUIElement>>,
+) {
+    if layout_scale.is_changed() {
+        for mut style in minimaps.iter_mut() {
+            style.size = minimap_size(&layout_scale);
+        }
+    }
+}
+
+/// Redraw the minimap around the player
+pub(crate) fn update_minimap(
+    mut minimap: ResMut<Minimap>,
+    mut images: ResMut<Assets<Image>>,
+    atlases: Res<Assets<TextureAtlas>>,
+    map_atlas: Res<MapAtlas>,
+    sources: MapSources,
+) {
+    let MapSources {
+        world,
+        procgen,
+        player,
+        npcs,
+    } = sources;
+    let Ok(pt) = player.get_single() else {
+        return;
+    };
+    let here = rendering_to_tile(pt.translation.x, pt.translation.y);
+
+    if minimap.colors.is_empty() {
+        match atlases
+            .get(&map_atlas.atlas)
+            .and_then(|atlas| images.get(&atlas.texture))
+        {
+            Some(sheet) => minimap.colors = tile_colors(sheet, &procgen.tiles),
+            // Still loading, try again next frame
+            None => return,
+        }
+    }
+
+    let view = MapView::around(here.chunk, MINIMAP_RADIUS, MINIMAP_SCALE);
+    let seen = discovered(&world)
+        .filter(|chunk| view.contains(chunk.position))
+        .map(|chunk| chunk.seen.len())
+        .sum();
+    if minimap.drawn_at == Some((here, seen)) {
+        return;
+    }
+    if let Some(image) = images.get_mut(&minimap.image) {
+        view.draw(
+            image,
+            &world,
+            &procgen.tiles,
+            &minimap.colors,
+            &here,
+            &wandering_quest_givers(&npcs),
+        );
+        minimap.drawn_at = Some((here, seen));
+    }
+}
+
+pub(crate) fn despawn_minimap(
+    mut commands: Commands,
+    ui_elements: Query<Entity, With<MinimapUIElement>>,
+) {
+    ui_elements.for_each(|elem| {
+        commands.entity(elem).despawn_recursive();
+    });
+}
+
+/// Open the map screen with the M key
+fn handle_open_map(mut commands: Commands, input: Res<Input<KeyCode>>) {
+    if input.just_pressed(KeyCode::M) {
+        commands.insert_resource(NextState(GameState::Map));
+    }
+}
+
+/// Draw every tile seen so far as big as fits on screen, with a legend and the names
+/// of the landmarks found so far
+pub(crate) fn setup_map(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    mut images: ResMut<Assets<Image>>,
+    windows: Res<Windows>,
+    layout_scale: Res<LayoutScale>,
+    minimap: Res<Minimap>,
+    sources: MapSources,
+) {
+    let MapSources {
+        world,
+        procgen,
+        player,
+        npcs,
+    } = sources;
+    let Ok(pt) = player.get_single() else {
+        error!("No player found?");
+        commands.insert_resource(NextState(GameState::Playing));
+        return;
+    };
+
+    // The picture is sized in real pixels so its tiles stay whole, the rest grows with the window
+    let window = windows
+        .get_primary()
+        .map_or((WIN_W, WIN_H), |window| (window.width(), window.height()));
+    let s = layout_scale.0;
+    let room = (
+        window.0 - 2. * MINIMAP_MARGIN * s,
+        window.1 - (MAP_TOP + MAP_BOTTOM) * s,
+    );
+    let view = MapView::discovered(&world, room.0, room.1);
+    let mut image = blank_image();
+    view.draw(
+        &mut image,
+        &world,
+        &procgen.tiles,
+        &minimap.colors,
+        &rendering_to_tile(pt.translation.x, pt.translation.y),
+        &wandering_quest_givers(&npcs),
+    );
+    let (width, height) = view.size();
+    let (width, height) = (width as f32, height as f32);
+
+    let font = asset_server.load("buttons/PressStart2P.ttf");
+    let style = |color: [u8; 4]| TextStyle {
+        font: font.clone(),
+        font_size: 20.0,
+        color: Color::rgb_u8(color[0], color[1], color[2]),
+    };
+
+    let mut landmarks = discovered(&world)
+        .filter_map(|chunk| {
+            let (x, y) = chunk.position;
+            Some(format!("{} ({}, {})", chunk.landmark.as_ref()?, x, y))
+        })
+        .collect::<Vec<String>>();
+    landmarks.sort();
+    let landmarks = if landmarks.is_empty() {
+        "No landmarks found yet".to_string()
+    } else {
+        format!("Landmarks: {}", landmarks.join(", "))
+    };
+
+    commands
+        .spawn_bundle(NodeBundle {
+            style: Style {
+                size: Size::new(Val::Percent(100.), Val::Percent(100.)),
+                position_type: PositionType::Absolute,
+                ..default()
+            },
+            color: Color::rgb(0.05, 0.05, 0.05).into(),
+            ..default()
+        })
+        .with_children(|parent| {
+            parent.spawn_bundle(
+                TextBundle::from_section("MAP", style([255, 255, 255, 255])).with_style(Style {
+                    position_type: PositionType::Absolute,
+                    position: UiRect {
+                        top: vh(25.),
+                        left: Val::Percent(50.),
+                        ..default()
+                    },
+                    // Half the width of the title
+                    margin: UiRect {
+                        left: Val::Px(-30. * s),
+                        ..default()
+                    },
+                    ..default()
+                }),
+            );
+
+            parent.spawn_bundle(ImageBundle {
+                style: Style {
+                    size: Size::new(Val::Px(width), Val::Px(height)),
+                    position_type: PositionType::Absolute,
+                    position: UiRect {
+                        top: Val::Px(MAP_TOP * s + (room.1 - height).max(0.) / 2.),
+                        left: Val::Px((window.0 - width).max(0.) / 2.),
+                        ..default()
+                    },
+                    ..default()
+                },
+                image: images.add(image).into(),
+                ..default()
+            });
+
+            parent.spawn_bundle(
+                TextBundle::from_sections([
+                    TextSection::new("You  ", style(PLAYER_MARKER)),
+                    TextSection::new("Healing  ", style(HEAL_MARKER)),
+                    TextSection::new("Quests  ", style(QUEST_MARKER)),
+                    TextSection::new("Landmarks", style(LANDMARK_MARKER)),
+                    TextSection::new(
+                        format!("\n\n{}\n\nM or Esc: back", landmarks),
+                        style([255, 255, 255, 255]),
+                    ),
+                ])
+                .with_style(Style {
+                    position_type: PositionType::Absolute,
+                    position: UiRect {
+                        bottom: vh(15.),
+                        left: vw(MINIMAP_MARGIN),
+                        ..default()
+                    },
+                    ..default()
+                }),
+            );
+        })
+        .insert(MapUIElement);
+}
+
+/// Close the map screen with the M or Esc key
+fn handle_exit_map(mut commands: Commands, input: Res<Input<KeyCode>>) {
+    if input.just_pressed(KeyCode::M) || input.just_pressed(KeyCode::Escape) {
+        commands.insert_resource(NextState(GameState::Playing));
+    }
+}
+
+pub(crate) fn despawn_map(mut commands: Commands, ui_elements: Query<Entity, With<MapUIElement>>) {
+    ui_elements.for_each(|elem| {
+        commands.entity(elem).despawn_recursive();
+    });
+}
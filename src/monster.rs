@@ -1,19 +1,6 @@
 use bevy::prelude::*;
-use rand::distributions::{Distribution, Standard};
 use serde::{Deserialize, Serialize};
-
-// Elemental types
-#[derive(Component, Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
-pub(crate) enum Element {
-    Scav,
-    Growth,
-    Ember,
-    Flood,
-    Rad,
-    Robot,
-    Clean,
-    Filth,
-}
+pub(crate) use waste::element::Element;
 
 // stats, Components used for MonsterBundle
 #[derive(Component, Copy, Clone, Serialize, Deserialize)]
@@ -137,21 +124,3 @@ pub(crate) fn get_number_from_type(elm: Element) -> usize {
         Element::Filth => 7,
     }
 }
-// elements enum to be able to pick randomly which type we want
-impl Distribution<Element> for Standard {
-    /// Randomly sample the element enum
-    fn sample<R: rand::Rng + ?Sized>(&self, rng: &mut R) -> Element {
-        // Randomly generate a number from 0 to 7 then return an enum variant
-        // corresponding to that.
-        match rng.gen_range(0..=7) {
-            0 => Element::Scav,
-            1 => Element::Growth,
-            2 => Element::Ember,
-            3 => Element::Flood,
-            4 => Element::Rad,
-            5 => Element::Robot,
-            6 => Element::Clean,
-            _ => Element::Filth,
-        }
-    }
-}
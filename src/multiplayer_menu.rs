@@ -5,6 +5,7 @@ use crate::game_client::{
     self, get_addr, get_randomized_port, ClientMarker, GameClient, HostMarker, PlayerType,
     SocketInfo,
 };
+use crate::layout::{vh, vw};
 use crate::networking::{MultiplayerMode, MultiplayerModeSelected};
 use crate::player::Player;
 use crate::GameState;
@@ -160,7 +161,7 @@ fn setup_mult(
     commands
         .spawn_bundle(ButtonBundle {
             style: Style {
-                size: Size::new(Val::Px(400.0), Val::Px(65.0)),
+                size: Size::new(vw(400.0), vh(65.0)),
                 // center button
                 margin: UiRect::all(Val::Auto),
                 // horizontally center child text
@@ -169,8 +170,8 @@ fn setup_mult(
                 align_items: AlignItems::Center,
                 position_type: PositionType::Absolute,
                 position: UiRect {
-                    bottom: Val::Px(450.),
-                    left: Val::Px((WIN_W * 0.685) / 2.),
+                    bottom: vh(450.),
+                    left: vw((WIN_W * 0.685) / 2.),
                     ..default()
                 },
                 ..default()
@@ -195,7 +196,7 @@ fn setup_mult(
     commands
         .spawn_bundle(ButtonBundle {
             style: Style {
-                size: Size::new(Val::Px(400.0), Val::Px(65.0)),
+                size: Size::new(vw(400.0), vh(65.0)),
                 // center button
                 margin: UiRect::all(Val::Auto),
                 // horizontally center child text
@@ -204,8 +205,8 @@ fn setup_mult(
                 align_items: AlignItems::Center,
                 position_type: PositionType::Absolute,
                 position: UiRect {
-                    bottom: Val::Px(350.),
-                    left: Val::Px((WIN_W * 0.685) / 2.),
+                    bottom: vh(350.),
+                    left: vw((WIN_W * 0.685) / 2.),
                     ..default()
                 },
                 ..default()
@@ -230,7 +231,7 @@ fn setup_mult(
     commands
         .spawn_bundle(ButtonBundle {
             style: Style {
-                size: Size::new(Val::Px(400.0), Val::Px(65.0)),
+                size: Size::new(vw(400.0), vh(65.0)),
                 // center button
                 margin: UiRect::all(Val::Auto),
                 // horizontally center child text
@@ -239,8 +240,8 @@ fn setup_mult(
                 align_items: AlignItems::Center,
                 position_type: PositionType::Absolute,
                 position: UiRect {
-                    bottom: Val::Px(250.),
-                    left: Val::Px((WIN_W * 0.685) / 2.),
+                    bottom: vh(250.),
+                    left: vw((WIN_W * 0.685) / 2.),
                     ..default()
                 },
                 ..default()
@@ -265,7 +266,7 @@ fn setup_mult(
     commands
         .spawn_bundle(ButtonBundle {
             style: Style {
-                size: Size::new(Val::Px(400.0), Val::Px(65.0)),
+                size: Size::new(vw(400.0), vh(65.0)),
                 // center button
                 margin: UiRect::all(Val::Auto),
                 // horizontally center child text
@@ -274,8 +275,8 @@ fn setup_mult(
                 align_items: AlignItems::Center,
                 position_type: PositionType::Absolute,
                 position: UiRect {
-                    bottom: Val::Px(150.),
-                    left: Val::Px((WIN_W * 0.685) / 2.),
+                    bottom: vh(150.),
+                    left: vw((WIN_W * 0.685) / 2.),
                     ..default()
                 },
                 ..default()
@@ -315,8 +316,8 @@ pub(crate) fn mult_options(mut commands: Commands, asset_server: Res<AssetServer
                 align_self: AlignSelf::FlexEnd,
                 position_type: PositionType::Absolute,
                 position: UiRect {
-                    top: Val::Px(125.0),
-                    left: Val::Px((WIN_W * 0.3) / 2.),
+                    top: vh(125.0),
+                    left: vw((WIN_W * 0.3) / 2.),
                     ..default()
                 },
                 ..default()
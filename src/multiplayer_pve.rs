@@ -11,6 +11,7 @@ use crate::game_client::{
     self, get_randomized_port, EnemyMonsterSpawned, GameClient, PlayerType, ReadyToSpawnEnemy,
     ReadyToSpawnFriend,
 };
+use crate::layout::{vh, vw};
 use crate::monster::{
     get_monster_sprite_for_type, Boss, Defense, Element, Enemy, Health, Level, MonsterStats, Moves,
     PartyMonster, SelectedMonster, Strength,
@@ -462,8 +463,8 @@ pub(crate) fn setup_pve_battle_stats(
                 align_self: AlignSelf::FlexEnd,
                 position_type: PositionType::Absolute,
                 position: UiRect {
-                    top: Val::Px(5.0),
-                    left: Val::Px(15.0),
+                    top: vh(5.0),
+                    left: vw(15.0),
                     ..default()
                 },
                 ..default()
@@ -496,8 +497,8 @@ pub(crate) fn setup_pve_battle_stats(
                 align_self: AlignSelf::FlexEnd,
                 position_type: PositionType::Absolute,
                 position: UiRect {
-                    top: Val::Px(5.0),
-                    left: Val::Px(425.0),
+                    top: vh(5.0),
+                    left: vw(425.0),
                     ..default()
                 },
                 ..default()
@@ -530,8 +531,8 @@ pub(crate) fn setup_pve_battle_stats(
                 align_self: AlignSelf::FlexEnd,
                 position_type: PositionType::Absolute,
                 position: UiRect {
-                    top: Val::Px(5.0),
-                    right: Val::Px(15.0),
+                    top: vh(5.0),
+                    right: vw(15.0),
                     ..default()
                 },
                 ..default()
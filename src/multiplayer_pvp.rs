@@ -5,6 +5,7 @@ use crate::camera::MultCamera;
 use crate::game_client::{
     EnemyMonsterSpawned, GameClient, ReadyToSpawnEnemy,
 };
+use crate::layout::{vh, vw};
 use crate::monster::{
     get_monster_sprite_for_type, Defense, Element, Health, Level, MonsterStats, Moves, SelectedMonster, Strength,
 };
@@ -1016,8 +1017,8 @@ pub(crate) fn setup_mult_battle_stats(
                 align_self: AlignSelf::FlexEnd,
                 position_type: PositionType::Absolute,
                 position: UiRect {
-                    top: Val::Px(5.0),
-                    left: Val::Px(15.0),
+                    top: vh(5.0),
+                    left: vw(15.0),
                     ..default()
                 },
                 ..default()
@@ -1050,8 +1051,8 @@ pub(crate) fn setup_mult_battle_stats(
                 align_self: AlignSelf::FlexEnd,
                 position_type: PositionType::Absolute,
                 position: UiRect {
-                    top: Val::Px(5.0),
-                    right: Val::Px(15.0),
+                    top: vh(5.0),
+                    right: vw(15.0),
                     ..default()
                 },
                 ..default()
@@ -1,4 +1,5 @@
 use crate::camera::MultWaitingCamera;
+use crate::layout::{vh, vw};
 use crate::{
     backgrounds::WIN_W,
     game_client::{HostNotReady, HostReady, ReadyToSpawnEnemy},
@@ -88,8 +89,8 @@ pub(crate) fn mult_waiting_text(mut commands: Commands, asset_server: Res<AssetS
                 align_self: AlignSelf::FlexEnd,
                 position_type: PositionType::Absolute,
                 position: UiRect {
-                    top: Val::Px(125.0),
-                    left: Val::Px((WIN_W * 0.3) / 2.),
+                    top: vh(125.0),
+                    left: vw((WIN_W * 0.3) / 2.),
                     ..default()
                 },
                 ..default()
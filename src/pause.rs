@@ -1,5 +1,6 @@
 use crate::backgrounds::{Tile, WIN_W};
 use crate::camera::MainCamera;
+use crate::layout::{vh, vw};
 use crate::player::Player;
 use crate::start_menu::{HOVERED_BUTTON, NORMAL_BUTTON, PRESSED_BUTTON, TEXT_COLOR};
 use crate::GameState;
@@ -81,8 +82,8 @@ pub(crate) fn setup_pause(
         .insert(Style {
             position_type: PositionType::Absolute,
             position: UiRect {
-                bottom: Val::Px(700.0),
-                left: Val::Px(560.0),
+                bottom: vh(700.0),
+                left: vw(560.0),
                 ..default()
             },
             ..default()
@@ -93,7 +94,7 @@ pub(crate) fn setup_pause(
     commands
         .spawn_bundle(ButtonBundle {
             style: Style {
-                size: Size::new(Val::Px(300.0), Val::Px(65.0)),
+                size: Size::new(vw(300.0), vh(65.0)),
                 // center button
                 margin: UiRect::all(Val::Auto),
                 // horizontally center child text
@@ -101,8 +102,8 @@ pub(crate) fn setup_pause(
                 // vertically center child text
                 align_items: AlignItems::Center,
                 position: UiRect {
-                    bottom: Val::Px(125.),
-                    left: Val::Px(0.),
+                    bottom: vh(125.),
+                    left: vw(0.),
                     ..default()
                 },
                 ..default()
@@ -127,7 +128,7 @@ pub(crate) fn setup_pause(
     commands
         .spawn_bundle(ButtonBundle {
             style: Style {
-                size: Size::new(Val::Px(225.0), Val::Px(65.0)),
+                size: Size::new(vw(225.0), vh(65.0)),
                 // center button
                 margin: UiRect::all(Val::Auto),
                 // horizontally center child text
@@ -136,8 +137,8 @@ pub(crate) fn setup_pause(
                 align_items: AlignItems::Center,
                 position_type: PositionType::Absolute,
                 position: UiRect {
-                    bottom: Val::Px(400.),
-                    left: Val::Px((WIN_W * 0.825) / 2.),
+                    bottom: vh(400.),
+                    left: vw((WIN_W * 0.825) / 2.),
                     ..default()
                 },
                 ..default()
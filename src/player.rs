@@ -1,24 +1,33 @@
 use std::io;
+use std::marker::PhantomData;
 
-use crate::backgrounds::{ChestTile, HealingTile, MonsterTile, Tile};
+use crate::backgrounds::{ChestTile, HealingTile, MonsterTile, Tile, TilePosition, TILE_SIZE};
+use crate::clock::{WorldClock, NIGHT_LEVELS};
 use crate::monster::{Boss, Defense, Enemy, Health, Level, MonsterStats, Strength};
 use crate::quests::NPC;
 use crate::world::{
-    item_index_to_name, rendering_to_logical, GameProgress, PooledText, TextBuffer,
+    item_index_to_name, rendering_to_logical, GameProgress, PooledText, TextBuffer, WorldMap,
 };
 use crate::GameState;
-use bevy::{prelude::*, sprite::collide_aabb::collide};
+use bevy::{ecs::system::SystemParam, prelude::*, sprite::collide_aabb::collide};
 use iyes_loopless::state::NextState;
 use rand::*;
+use waste::biomes::biome_at;
+use waste::tiles::TileRegistry;
+use waste::wfc::ProcGen;
 // original 8px/frame movement equalled 480 px/sec.
 // frame-independent movement is in px/second (480 px/sec.)
 pub(crate) const PLAYER_SPEED: f32 = 480.;
 // We'll wanna replace these with animated sprite sheets later
 pub(crate) const ANIM_TIME: f32 = 0.15;
 pub(crate) const ANIM_FRAMES: usize = 4;
+/// Width and height of the box the player collides with terrain and special tiles with
+pub(crate) const PLAYER_HITBOX: f32 = 32.;
 #[derive(Component)]
 pub(crate) struct Player {
     pub(crate) current_chunk: (isize, isize),
+    // direction the player last moved in, used to generate chunks ahead of them first
+    pub(crate) heading: Vec2,
 }
 
 #[derive(Component, Deref, DerefMut)]
@@ -79,6 +88,58 @@ pub(crate) fn animate_sprite(
     }
 }
 
+/// Check if the player's hitbox, centered on `position`, only covers walkable tiles
+fn fits_at(world: &WorldMap, tiles: &TileRegistry, position: Vec2) -> bool {
+    let half = PLAYER_HITBOX / 2.;
+    [(-half, -half), (-half, half), (half, -half), (half, half)]
+        .iter()
+        .all(|(dx, dy)| world.walkable_at(tiles, position.x + dx, position.y + dy))
+}
+
+/// Special tiles of kind `T` the player can walk into, and which tile each one is
+type SpecialTiles<'w, 's, T> =
+    Query<'w, 's, (Entity, &'static Transform, &'static TilePosition), (With<T>, Without<Player>)>;
+
+/// NPCs with the quest they give out, and the tile they stand on if they're part of one
+type Npcs<'w, 's> = Query<
+    'w,
+    's,
+    (
+        Entity,
+        &'static Transform,
+        &'static NPC,
+        Option<&'static TilePosition>,
+    ),
+    (
+        With<NPC>,
+        Without<Player>,
+        Without<MonsterTile>,
+        Without<HealingTile>,
+        Without<ChestTile>,
+    ),
+>;
+
+/// Special tiles and NPCs the player can walk into, and the party a healing tile patches up
+#[derive(SystemParam)]
+pub(crate) struct Encounters<'w, 's> {
+    monster_tiles: SpecialTiles<'w, 's, MonsterTile>,
+    healing_tiles: SpecialTiles<'w, 's, HealingTile>,
+    chest_tiles: SpecialTiles<'w, 's, ChestTile>,
+    npcs: Npcs<'w, 's>,
+    monster_hp: Query<'w, 's, &'static mut Health, Without<Enemy>>,
+}
+
+/// The world the player walks around in, and the text box telling them what happens there
+#[derive(SystemParam)]
+pub(crate) struct Overworld<'w, 's> {
+    world: ResMut<'w, WorldMap>,
+    procgen: Res<'w, ProcGen>,
+    clock: Res<'w, WorldClock>,
+    text_buffer: ResMut<'w, TextBuffer>,
+    #[system_param(ignore)]
+    marker: PhantomData<&'s ()>,
+}
+
 pub(crate) fn move_player(
     input: Res<Input<KeyCode>>,
     time: Res<Time>,
@@ -87,23 +148,24 @@ pub(crate) fn move_player(
         (&mut Player, &mut Transform),
         (With<Player>, Without<Tile>, Without<MonsterTile>),
     >,
-    monster_tiles: Query<(Entity, &Transform), (With<MonsterTile>, Without<Player>)>,
-    healing_tiles: Query<(Entity, &Transform), (With<HealingTile>, Without<Player>)>,
-    chest_tiles: Query<(Entity, &Transform), (With<ChestTile>, Without<Player>)>,
-    mut monster_hp: Query<&mut Health, Without<Enemy>>,
     mut game_progress: ResMut<GameProgress>,
-    npcs: Query<
-        (Entity, &Transform, &NPC),
-        (
-            With<NPC>,
-            Without<Player>,
-            Without<MonsterTile>,
-            Without<HealingTile>,
-            Without<ChestTile>,
-        ),
-    >,
-    mut text_buffer: ResMut<TextBuffer>,
+    encounters: Encounters,
+    overworld: Overworld,
 ) {
+    let Encounters {
+        monster_tiles,
+        healing_tiles,
+        chest_tiles,
+        npcs,
+        mut monster_hp,
+    } = encounters;
+    let Overworld {
+        mut world,
+        procgen,
+        clock,
+        mut text_buffer,
+        ..
+    } = overworld;
     if player.is_empty() {
         error!("Couldn't find a player to move...");
         return;
@@ -171,6 +233,14 @@ pub(crate) fn move_player(
             pooled: false,
         };
         text_buffer.bottom_text.push_back(text);
+        let text = PooledText {
+            text: format!(
+                "Screens explored: {} Tiles walked: {:.0}.",
+                game_progress.chunks_discovered, game_progress.distance_travelled
+            ),
+            pooled: false,
+        };
+        text_buffer.bottom_text.push_back(text);
     }
 
     // Get current coords
@@ -188,18 +258,36 @@ pub(crate) fn move_player(
 
     // Most of these numbers come from debugging
     // and seeing what works.
-    pt.translation.x += x_vel;
+    // Each axis is checked against the terrain on its own, so the player
+    // slides along walls instead of sticking to them. If the player is
+    // somehow already inside solid terrain, let them walk out of it.
+    let start = pt.translation;
+    let stuck = !fits_at(&world, &procgen.tiles, pt.translation.truncate());
+    let next_x = Vec2::new(pt.translation.x + x_vel, pt.translation.y);
+    if stuck || fits_at(&world, &procgen.tiles, next_x) {
+        pt.translation.x += x_vel;
+    }
+
+    let next_y = Vec2::new(pt.translation.x, pt.translation.y + y_vel);
+    if stuck || fits_at(&world, &procgen.tiles, next_y) {
+        pt.translation.y += y_vel;
+    }
+
+    game_progress.distance_travelled += pt.translation.distance(start) / TILE_SIZE;
+    pd.current_chunk = rendering_to_logical(pt.translation.x, pt.translation.y);
 
-    pt.translation.y += y_vel;
+    if x_vel != 0. || y_vel != 0. {
+        pd.heading = Vec2::new(x_vel, y_vel).normalize();
+    }
 
     // This is where we will check for collisions with monsters
 
     // This is awful, can we do this without loops?
-    for (monster_tile, tile_pos) in monster_tiles.iter() {
+    for (monster_tile, tile_pos, position) in monster_tiles.iter() {
         let mt_position = tile_pos.translation;
         let collision = collide(
             pt.translation,
-            Vec2::splat(32.),
+            Vec2::splat(PLAYER_HITBOX),
             mt_position,
             Vec2::splat(32.),
         );
@@ -211,23 +299,30 @@ pub(crate) fn move_player(
                 // switches from Playing -> Battle state
                 // The level_boss_awaken bool is by default false
                 // it will appear after we level up(defeat 5 monsters)
+                // Monsters take after the biome they live in, and the time of day
+                let (cx, cy) = position.chunk;
+                let biome = world
+                    .biome(cx, cy)
+                    .unwrap_or_else(|| biome_at(procgen.seed, cx, cy));
+                let typing = biome.random_element(&mut thread_rng(), clock.is_night());
                 if !game_progress.level_boss_awaken {
-                    // Normal monster
+                    // Normal monster, tougher at night
+                    let level = game_progress.current_level
+                        + if clock.is_night() { NIGHT_LEVELS } else { 0 };
                     let enemy_stats = MonsterStats {
-                        lvl: Level {
-                            level: game_progress.current_level,
-                        },
+                        typing,
+                        lvl: Level { level },
                         hp: Health {
-                            health: (game_progress.current_level * 10) as isize,
-                            max_health: (game_progress.current_level) * 10,
+                            health: (level * 10) as isize,
+                            max_health: level * 10,
                         },
                         stg: Strength {
-                            atk: (game_progress.current_level) * 2,
-                            crt: game_progress.current_level * 5,
+                            atk: level * 2,
+                            crt: level * 5,
                             crt_dmg: 2,
                         },
                         def: Defense {
-                            def: game_progress.current_level,
+                            def: level,
                             crt_res: 10,
                         },
                         ..Default::default()
@@ -241,6 +336,7 @@ pub(crate) fn move_player(
                 } else {
                     // Boss monster
                     let enemy_stats = MonsterStats {
+                        typing,
                         lvl: Level {
                             level: game_progress.current_level,
                         },
@@ -269,17 +365,18 @@ pub(crate) fn move_player(
                     game_progress.enemy_stats.insert(enemy_entity, enemy_stats);
                 }
                 commands.entity(monster_tile).remove::<MonsterTile>();
+                world.use_special(*position);
                 commands.insert_resource(NextState(GameState::Battle));
             }
         }
     }
 
     // check for healing tiles
-    for (healing_tile, tile_pos) in healing_tiles.iter() {
+    for (healing_tile, tile_pos, position) in healing_tiles.iter() {
         let ht_position = tile_pos.translation;
         let collision = collide(
             pt.translation,
-            Vec2::splat(32.),
+            Vec2::splat(PLAYER_HITBOX),
             ht_position,
             Vec2::splat(32.),
         );
@@ -297,16 +394,17 @@ pub(crate) fn move_player(
                 };
                 text_buffer.bottom_text.push_back(text);
                 commands.entity(healing_tile).remove::<HealingTile>();
+                world.use_special(*position);
             }
         }
     }
 
     // check for chest tiles
-    for (chest_tile, tile_pos) in chest_tiles.iter() {
+    for (chest_tile, tile_pos, position) in chest_tiles.iter() {
         let ht_position = tile_pos.translation;
         let collision = collide(
             pt.translation,
-            Vec2::splat(32.),
+            Vec2::splat(PLAYER_HITBOX),
             ht_position,
             Vec2::splat(32.),
         );
@@ -322,15 +420,16 @@ pub(crate) fn move_player(
                 text_buffer.bottom_text.push_back(text);
                 game_progress.player_inventory[item] += 1;
                 commands.entity(chest_tile).remove::<ChestTile>();
+                world.use_special(*position);
             }
         }
     }
 
-    for (npc_entity, npc_pos, npc_data) in npcs.iter() {
+    for (npc_entity, npc_pos, npc_data, position) in npcs.iter() {
         let npc_position = npc_pos.translation;
         let collision = collide(
             pt.translation,
-            Vec2::splat(32.),
+            Vec2::splat(PLAYER_HITBOX),
             npc_position,
             Vec2::splat(32.),
         );
@@ -353,7 +452,14 @@ pub(crate) fn move_player(
                 };
                 text_buffer.bottom_text.push_back(text);
                 game_progress.add_active_quest(quest);
-                commands.entity(npc_entity).despawn();
+                // NPCs standing on a tile are the tile's sprite, which has to stay drawn.
+                // They don't come back when their chunk is redrawn.
+                if let Some(position) = position {
+                    commands.entity(npc_entity).remove::<NPC>();
+                    world.use_special(*position);
+                } else {
+                    commands.entity(npc_entity).despawn();
+                }
             }
         }
     }
@@ -0,0 +1,284 @@
+use crate::tiles::{TileRegistry, TILE_REGISTRY};
+use crate::wfc::{input_files, MAP_HEIGHT, MAP_WIDTH};
+use crate::wfc_input::{error, field, number, tile, words, WfcInputError};
+use std::fs::read_to_string;
+use std::path::Path;
+
+/// Folder the hand-made landmarks are read from
+pub const PREFABS: &str = "assets/backgrounds/prefabs";
+/// Current version of the prefab format
+pub const PREFAB_VERSION: usize = 1;
+/// First word of a prefab file
+const MAGIC: &str = "waste-prefab";
+/// Word on a prefab board for a tile WFC gets to pick
+const FREE: &str = ".";
+
+/// Hand-made structure that gets stamped into the chunks at fixed logical positions,
+/// like the arenas guarding the Cs-137. WFC generates the rest of those chunks around it.
+///
+/// Files look like
+/// ```text
+/// waste-prefab 1
+/// name cs137_arena
+/// tileset backgrounds/overworld_tiles.txt
+/// at 9,3
+/// at -5,10
+/// board
+/// . 0 0 0 .
+/// 0 9 9 9 0
+/// 0 9 33 9 0
+/// . 0 0 0 .
+/// ```
+/// Every line up to `board` is required, and `at <x>,<y>` may be repeated to place the prefab
+/// in several chunks. The board goes in the middle of the chunk, and `.` leaves a tile up to WFC.
+/// Boards must leave at least one tile free along every side of the chunk, so the chunk can
+/// still line up with its neighbors. `#` starts a comment.
+#[derive(Debug, Clone)]
+pub struct Prefab {
+    pub name: String,
+    /// Logical positions of the chunks the prefab is stamped into
+    pub positions: Vec<(isize, isize)>,
+    /// Tiles in row major order, `None` where WFC picks
+    pub board: Vec<Vec<Option<usize>>>,
+}
+
+impl Prefab {
+    /// Read a prefab file's contents, checking every tile against the tile registry
+    pub fn parse(contents: &str, tiles: &TileRegistry) -> Result<Self, WfcInputError> {
+        let mut lines = contents
+            .lines()
+            .enumerate()
+            .map(|(i, line)| (i + 1, words(line)))
+            .filter(|(_, words)| !words.is_empty());
+
+        let (line, first) = lines
+            .next()
+            .ok_or_else(|| error(1, 1, "prefab is empty".to_string()))?;
+        if first[0].1 != MAGIC {
+            return Err(error(
+                line,
+                first[0].0,
+                format!("expected `{} {}`", MAGIC, PREFAB_VERSION),
+            ));
+        }
+        let (column, version) = field(line, &first, 1)?;
+        let version = number(line, column, version)?;
+        if version != PREFAB_VERSION {
+            return Err(error(
+                line,
+                column,
+                format!(
+                    "unsupported version {}, expected {}",
+                    version, PREFAB_VERSION
+                ),
+            ));
+        }
+
+        let mut name = None;
+        let mut tileset = false;
+        let mut positions = Vec::new();
+        let mut last_line = line;
+        let mut board_line = None;
+        for (line, words) in lines.by_ref() {
+            last_line = line;
+            let (column, key) = words[0];
+            match key {
+                "board" => {
+                    board_line = Some((line, column));
+                    break;
+                }
+                "name" => name = Some(field(line, &words, 1)?.1.to_string()),
+                "tileset" => {
+                    let (column, path) = field(line, &words, 1)?;
+                    if path != TILE_REGISTRY {
+                        return Err(error(
+                            line,
+                            column,
+                            format!("prefab is for tileset `{}`, not `{}`", path, TILE_REGISTRY),
+                        ));
+                    }
+                    tileset = true;
+                }
+                "at" => {
+                    let (column, at) = field(line, &words, 1)?;
+                    let invalid = || error(line, column, format!("`{}` is not like 9,3", at));
+                    let (x, y) = at.split_once(',').ok_or_else(invalid)?;
+                    positions.push((
+                        x.parse().map_err(|_| invalid())?,
+                        y.parse().map_err(|_| invalid())?,
+                    ));
+                }
+                _ => return Err(error(line, column, format!("unknown header `{}`", key))),
+            }
+        }
+
+        let (line, column) = board_line.ok_or_else(|| {
+            error(
+                last_line + 1,
+                1,
+                "header never ends, missing `board` line".to_string(),
+            )
+        })?;
+        let missing = |what: &str| error(line, column, format!("missing `{}` line", what));
+        let name = name.ok_or_else(|| missing("name"))?;
+        if !tileset {
+            return Err(missing("tileset"));
+        }
+        if positions.is_empty() {
+            return Err(missing("at"));
+        }
+
+        let mut board: Vec<Vec<Option<usize>>> = Vec::new();
+        for (line, words) in lines {
+            let row = words
+                .iter()
+                .map(|(column, word)| match *word {
+                    FREE => Ok(None),
+                    word => tile(line, *column, word, tiles).map(Some),
+                })
+                .collect::<Result<Vec<Option<usize>>, WfcInputError>>()?;
+            if let Some(first) = board.first() {
+                if row.len() != first.len() {
+                    return Err(error(
+                        line,
+                        words[0].0,
+                        format!(
+                            "row is {} tiles wide, but the first row is {}",
+                            row.len(),
+                            first.len()
+                        ),
+                    ));
+                }
+            }
+            // One free tile has to stay on either side
+            if row.len() > MAP_WIDTH - 2 || board.len() + 1 > MAP_HEIGHT - 2 {
+                return Err(error(
+                    line,
+                    words[0].0,
+                    format!(
+                        "board is bigger than {}x{}, the chunk without its border",
+                        MAP_WIDTH - 2,
+                        MAP_HEIGHT - 2
+                    ),
+                ));
+            }
+            board.push(row);
+        }
+        if board.is_empty() {
+            return Err(error(line, column, "board is empty".to_string()));
+        }
+
+        Ok(Self {
+            name,
+            positions,
+            board,
+        })
+    }
+
+    /// Tiles to seed a chunk with, as (tile, (row, column)) with the board centered in the chunk
+    pub fn seeding(&self) -> Vec<(usize, (usize, usize))> {
+        let top = (MAP_HEIGHT - self.board.len()) / 2;
+        let left = (MAP_WIDTH - self.board[0].len()) / 2;
+        let mut seeding = Vec::new();
+        for (row, line) in self.board.iter().enumerate() {
+            for (col, t) in line.iter().enumerate() {
+                if let Some(t) = t {
+                    seeding.push((*t, (top + row, left + col)));
+                }
+            }
+        }
+        seeding
+    }
+}
+
+/// Read every prefab under `dir` (normally 'assets/backgrounds/prefabs/').
+///
+/// Like WFC inputs, files that can't be read or parsed are skipped and the reason why is
+/// returned alongside the prefabs.
+pub fn read_prefabs(dir: &Path, tiles: &TileRegistry) -> (Vec<Prefab>, Vec<String>) {
+    let mut prefabs = Vec::new();
+    let mut skipped = Vec::new();
+    let files = input_files(dir).unwrap_or_else(|e| {
+        skipped.push(format!("Couldn't read prefabs in {}: {}", dir.display(), e));
+        Vec::new()
+    });
+    for path in files {
+        match read_to_string(&path) {
+            Ok(contents) => match Prefab::parse(&contents, tiles) {
+                Ok(prefab) => prefabs.push(prefab),
+                Err(e) => skipped.push(format!("Skipping prefab {}: {}", path.display(), e)),
+            },
+            Err(e) => skipped.push(format!("Skipping prefab {}: {}", path.display(), e)),
+        }
+    }
+    (prefabs, skipped)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::wfc::{ProcGen, WFC_INPUTS};
+
+    const HEADER: &str =
+        "waste-prefab 1\nname well\ntileset backgrounds/overworld_tiles.txt\nat 2,-1\nat -4,3\n";
+
+    #[test]
+    fn centers_the_board_in_the_chunk() {
+        let prefab = Prefab::parse(
+            &format!("{}board\n0 . 9\n9 0 0\n", HEADER),
+            &TileRegistry::default(),
+        )
+        .unwrap();
+        assert_eq!(prefab.positions, vec![(2, -1), (-4, 3)]);
+        assert_eq!(
+            prefab.board,
+            vec![
+                vec![Some(0), None, Some(9)],
+                vec![Some(9), Some(0), Some(0)]
+            ]
+        );
+        // 2 of 12 rows fit exactly in the middle, 3 of 20 columns lean to the left
+        assert_eq!(
+            prefab.seeding(),
+            vec![
+                (0, (5, 8)),
+                (9, (5, 10)),
+                (9, (6, 8)),
+                (0, (6, 9)),
+                (0, (6, 10))
+            ]
+        );
+    }
+
+    #[test]
+    fn rejects_a_board_that_reaches_the_border() {
+        let row = vec!["0"; MAP_WIDTH - 1].join(" ");
+        let e = Prefab::parse(
+            &format!("{}board\n{}\n", HEADER, row),
+            &TileRegistry::default(),
+        )
+        .unwrap_err();
+        assert_eq!((e.line, e.column), (7, 1));
+        assert!(e.message.contains("bigger than"), "{}", e);
+    }
+
+    #[test]
+    fn landmarks_get_stamped_into_their_chunks() {
+        let tiles = TileRegistry::default();
+        let (prefabs, skipped) = read_prefabs(Path::new(PREFABS), &tiles);
+        assert!(skipped.is_empty(), "{:?}", skipped);
+        let procgen = ProcGen::new(Path::new(WFC_INPUTS), Path::new(PREFABS), 99).0;
+        for prefab in &prefabs {
+            let (x, y) = prefab.positions[0];
+            let layout = procgen.generate(x, y);
+            assert_eq!(layout.landmark.as_deref(), Some(prefab.name.as_str()));
+            for (t, (row, col)) in prefab.seeding() {
+                assert_eq!(
+                    layout.tiles[row][col], t,
+                    "{} lost its tile at ({}, {})",
+                    prefab.name, row, col
+                );
+            }
+        }
+    }
+}
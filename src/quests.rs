@@ -23,9 +23,14 @@ pub(crate) struct Quest {
 impl Quest {
     /// Create a new quest object with a random target monster and random reward
     pub(crate) fn random() -> Self {
-        let target = rand::random();
-        let reward = rand::thread_rng().gen_range(0..NUM_ITEM_TYPES) as usize;
-        let reward_amount = rand::thread_rng().gen_range(1..=5) as usize;
+        Self::random_from(&mut rand::thread_rng())
+    }
+
+    /// Create a new quest object with a target monster and reward drawn from `rng`
+    pub(crate) fn random_from<R: Rng>(rng: &mut R) -> Self {
+        let target = rng.gen();
+        let reward = rng.gen_range(0..NUM_ITEM_TYPES);
+        let reward_amount = rng.gen_range(1..=5) as usize;
         Self {
             target,
             reward,
@@ -0,0 +1,308 @@
+use crate::tiles::TileRegistry;
+use crate::wfc::{
+    chunk_rng, wfc_patch, ChunkEdges, ProcGen, Rule, WfcError, MAP_HEIGHT, MAP_WIDTH,
+};
+use log::warn;
+use rand::rngs::StdRng;
+use std::collections::HashMap;
+
+/// Mixed into the world seed so the corners and both kinds of seams get random numbers
+/// unrelated to the chunks' own
+const CORNER_SALT: u64 = 0x5EED_C0FE_E000_0001;
+const VERTICAL_SEAM_SALT: u64 = 0x5EED_C0FE_E000_0002;
+const HORIZONTAL_SEAM_SALT: u64 = 0x5EED_C0FE_E000_0003;
+/// Times a seam that nothing can walk across gets picked again
+const MAX_SEAM_TRIES: usize = 3;
+
+/// Tiles picked for a chunk's border ahead of time, and for the borders of its neighbors facing it.
+///
+/// The cells on both sides of a seam between two chunks are picked together from a seed of
+/// their own, so every chunk can be generated without waiting for its neighbors and still
+/// line up with them, whatever order they get generated in.
+#[derive(Debug, Clone, Default)]
+pub struct Seams {
+    /// Tiles of the chunk's own border on each side in `Dir::ALL` order, in `wfc()` seeding form.
+    /// Corner cells are on both of their sides.
+    pub sides: [Vec<(usize, (usize, usize))>; 4],
+    /// Tiles of the neighbors' borders across from it
+    pub edges: ChunkEdges,
+}
+
+impl Seams {
+    /// Tiles of the chunk's own border on every side that is still in
+    pub fn seeding(&self) -> Vec<(usize, (usize, usize))> {
+        self.sides.concat()
+    }
+
+    /// Leave out the side closest to `cell`, both the chunk's own tiles and the neighbor's
+    /// across from them, along with the diagonal neighbors at either end of it,
+    /// for when the chunk can't be generated with all of them.
+    ///
+    /// Returns false if every side is already out.
+    pub fn leave_out_nearest(&mut self, (row, col): (usize, usize)) -> bool {
+        let distances = [col, row, MAP_WIDTH - 1 - col, MAP_HEIGHT - 1 - row];
+        let Some(side) = (0..4)
+            .filter(|side| !self.sides[*side].is_empty())
+            .min_by_key(|side| distances[*side])
+        else {
+            return false;
+        };
+        self.sides[side].clear();
+        let corners = match side {
+            0 => {
+                self.edges.west = None;
+                [0, 2]
+            }
+            1 => {
+                self.edges.north = None;
+                [0, 1]
+            }
+            2 => {
+                self.edges.east = None;
+                [1, 3]
+            }
+            _ => {
+                self.edges.south = None;
+                [2, 3]
+            }
+        };
+        for corner in corners {
+            self.edges.corners[corner] = None;
+        }
+        true
+    }
+}
+
+/// Tiles around the point where four chunks meet, north row first, each row west to east
+type Corner = [[usize; 2]; 2];
+
+/// Rules of a chunk and its eight neighbors, by logical position
+type NearbyRules = HashMap<(isize, isize), HashMap<usize, Rule>>;
+
+impl ProcGen {
+    /// Pick the border tiles of the chunk at (x, y) and of its neighbors where they face it.
+    ///
+    /// This only looks at the four corners and four seams around the chunk,
+    /// so it takes as long for a chunk far from the origin as for one next to it.
+    pub fn seams(&self, x: isize, y: isize) -> Seams {
+        let rules: NearbyRules = (-1..=1)
+            .flat_map(|dy| (-1..=1).map(move |dx| (x + dx, y + dy)))
+            .map(|(nx, ny)| ((nx, ny), self.chunk_rules(nx, ny).1))
+            .collect();
+
+        // Named after the corner or side of the chunk they're on
+        let north_west = self.corner(x - 1, y, &rules);
+        let north_east = self.corner(x, y, &rules);
+        let south_west = self.corner(x - 1, y - 1, &rules);
+        let south_east = self.corner(x, y - 1, &rules);
+        let west = self.vertical_seam(x - 1, y, north_west, south_west, &rules);
+        let east = self.vertical_seam(x, y, north_east, south_east, &rules);
+        let north = self.horizontal_seam(x, y, north_west, north_east, &rules);
+        let south = self.horizontal_seam(x, y - 1, south_west, south_east, &rules);
+
+        let (last_row, last_col) = (MAP_HEIGHT - 1, MAP_WIDTH - 1);
+        let own_corner = |corner: Option<Corner>, (row, col): (usize, usize), cell| {
+            corner.map(|corner| (corner[row][col], cell))
+        };
+        let top_left = own_corner(north_west, (1, 1), (0, 0));
+        let top_right = own_corner(north_east, (1, 0), (0, last_col));
+        let bottom_left = own_corner(south_west, (0, 1), (last_row, 0));
+        let bottom_right = own_corner(south_east, (0, 0), (last_row, last_col));
+
+        // Corner cells come from the corners even if a seam failed, so the seams only give
+        // the cells between them
+        let mut sides: [Vec<(usize, (usize, usize))>; 4] = Default::default();
+        if let Some(west) = &west {
+            sides[0].extend((1..last_row).map(|row| (west[row][1], (row, 0))));
+        }
+        if let Some(north) = &north {
+            sides[1].extend((1..last_col).map(|col| (north[1][col], (0, col))));
+        }
+        if let Some(east) = &east {
+            sides[2].extend((1..last_row).map(|row| (east[row][0], (row, last_col))));
+        }
+        if let Some(south) = &south {
+            sides[3].extend((1..last_col).map(|col| (south[0][col], (last_row, col))));
+        }
+        for (side, corners) in sides.iter_mut().zip([
+            [top_left, bottom_left],
+            [top_left, top_right],
+            [top_right, bottom_right],
+            [bottom_left, bottom_right],
+        ]) {
+            side.extend(corners.into_iter().flatten());
+        }
+
+        Seams {
+            sides,
+            edges: ChunkEdges {
+                north: north.map(|seam| seam[0].clone()),
+                south: south.map(|seam| seam[1].clone()),
+                east: east.map(|seam| seam.iter().map(|row| row[1]).collect()),
+                west: west.map(|seam| seam.iter().map(|row| row[0]).collect()),
+                corners: [
+                    north_west.map(|corner| corner[0][0]),
+                    north_east.map(|corner| corner[0][1]),
+                    south_west.map(|corner| corner[1][0]),
+                    south_east.map(|corner| corner[1][1]),
+                ],
+                ..Default::default()
+            },
+        }
+    }
+
+    /// Pick the tiles around the north east corner of the chunk at (x, y),
+    /// one from each of the four chunks that meet there
+    fn corner(&self, x: isize, y: isize, rules: &NearbyRules) -> Option<Corner> {
+        let chunks = [(x, y + 1), (x + 1, y + 1), (x, y), (x + 1, y)];
+        // The cell of each chunk that touches the corner
+        let cells = [
+            (MAP_HEIGHT - 1, MAP_WIDTH - 1),
+            (MAP_HEIGHT - 1, 0),
+            (0, MAP_WIDTH - 1),
+            (0, 0),
+        ];
+        let seeding = chunks
+            .iter()
+            .zip(cells)
+            .enumerate()
+            .filter_map(|(i, (chunk, cell))| {
+                self.landmark_tile(*chunk, cell)
+                    .map(|t| (t, (i / 2, i % 2)))
+            })
+            .collect();
+
+        let rule_sets = chunks.map(|chunk| &rules[&chunk]);
+        let mut rng = chunk_rng(self.seed ^ CORNER_SALT, x, y);
+        wfc_patch(
+            &[vec![0, 1], vec![2, 3]],
+            &rule_sets,
+            seeding,
+            &mut rng,
+            self.budget,
+        )
+        .map(|generated| {
+            let t = generated.tiles;
+            [[t[0][0], t[0][1]], [t[1][0], t[1][1]]]
+        })
+        .map_err(|e| warn!("Corner north east of chunk ({}, {}) failed: {}", x, y, e))
+        .ok()
+    }
+
+    /// Pick the tiles on both sides of the seam between the chunk at (x, y) and the one east of it,
+    /// as rows of its tile and the eastern neighbor's, continuing from the corners at either end
+    fn vertical_seam(
+        &self,
+        x: isize,
+        y: isize,
+        top: Option<Corner>,
+        bottom: Option<Corner>,
+        rules: &NearbyRules,
+    ) -> Option<Vec<Vec<usize>>> {
+        let chunks = [(x, y), (x + 1, y)];
+        let mut seeding = Vec::new();
+        for row in 0..MAP_HEIGHT {
+            for (side, col) in [(0, MAP_WIDTH - 1), (1, 0)] {
+                if let Some(t) = self.landmark_tile(chunks[side], (row, col)) {
+                    seeding.push((t, (row, side)));
+                }
+            }
+        }
+        if let Some(top) = top {
+            seeding.extend([(top[1][0], (0, 0)), (top[1][1], (0, 1))]);
+        }
+        if let Some(bottom) = bottom {
+            let last = MAP_HEIGHT - 1;
+            seeding.extend([(bottom[0][0], (last, 0)), (bottom[0][1], (last, 1))]);
+        }
+
+        let crossable = |tiles: &[Vec<usize>], registry: &TileRegistry| {
+            tiles
+                .iter()
+                .any(|row| row.iter().all(|t| registry.walkable(*t)))
+        };
+        self.seam(
+            &vec![vec![0, 1]; MAP_HEIGHT],
+            &chunks.map(|chunk| &rules[&chunk]),
+            seeding,
+            chunk_rng(self.seed ^ VERTICAL_SEAM_SALT, x, y),
+            crossable,
+        )
+        .map_err(|e| warn!("Seam east of chunk ({}, {}) failed: {}", x, y, e))
+        .ok()
+    }
+
+    /// Pick the tiles on both sides of the seam between the chunk at (x, y) and the one north of it,
+    /// as the northern neighbor's row followed by the chunk's, continuing from the corners at either end
+    fn horizontal_seam(
+        &self,
+        x: isize,
+        y: isize,
+        left: Option<Corner>,
+        right: Option<Corner>,
+        rules: &NearbyRules,
+    ) -> Option<Vec<Vec<usize>>> {
+        let chunks = [(x, y + 1), (x, y)];
+        let mut seeding = Vec::new();
+        for col in 0..MAP_WIDTH {
+            for (side, row) in [(0, MAP_HEIGHT - 1), (1, 0)] {
+                if let Some(t) = self.landmark_tile(chunks[side], (row, col)) {
+                    seeding.push((t, (side, col)));
+                }
+            }
+        }
+        if let Some(left) = left {
+            seeding.extend([(left[0][1], (0, 0)), (left[1][1], (1, 0))]);
+        }
+        if let Some(right) = right {
+            let last = MAP_WIDTH - 1;
+            seeding.extend([(right[0][0], (0, last)), (right[1][0], (1, last))]);
+        }
+
+        let crossable = |tiles: &[Vec<usize>], registry: &TileRegistry| {
+            (0..MAP_WIDTH).any(|col| tiles.iter().all(|row| registry.walkable(row[col])))
+        };
+        self.seam(
+            &[vec![0; MAP_WIDTH], vec![1; MAP_WIDTH]],
+            &chunks.map(|chunk| &rules[&chunk]),
+            seeding,
+            chunk_rng(self.seed ^ HORIZONTAL_SEAM_SALT, x, y),
+            crossable,
+        )
+        .map_err(|e| warn!("Seam north of chunk ({}, {}) failed: {}", x, y, e))
+        .ok()
+    }
+
+    /// Generate the cells along a seam, picking again while nothing can walk across it.
+    ///
+    /// A seam that never gets a crossing is kept anyway, `connect()` can't do anything
+    /// about it but the chunks on either side still line up.
+    fn seam(
+        &self,
+        owners: &[Vec<usize>],
+        rule_sets: &[&HashMap<usize, Rule>],
+        seeding: Vec<(usize, (usize, usize))>,
+        mut rng: StdRng,
+        crossable: impl Fn(&[Vec<usize>], &TileRegistry) -> bool,
+    ) -> Result<Vec<Vec<usize>>, WfcError> {
+        let mut tiles = wfc_patch(owners, rule_sets, seeding.clone(), &mut rng, self.budget)?.tiles;
+        for _ in 1..MAX_SEAM_TRIES {
+            if crossable(&tiles, &self.tiles) {
+                break;
+            }
+            tiles = wfc_patch(owners, rule_sets, seeding.clone(), &mut rng, self.budget)?.tiles;
+        }
+        Ok(tiles)
+    }
+
+    /// Tile the landmark of the chunk at `chunk` puts on `cell`, if it puts one there
+    fn landmark_tile(&self, chunk: (isize, isize), cell: (usize, usize)) -> Option<usize> {
+        self.landmarks.get(&chunk).and_then(|landmark| {
+            landmark
+                .seeding
+                .iter()
+                .find(|(_, c)| *c == cell)
+                .map(|(t, _)| *t)
+        })
+    }
+}
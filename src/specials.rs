@@ -0,0 +1,223 @@
+use crate::tiles::{TileInteraction, TileRegistry};
+use crate::wfc::{MAP_HEIGHT, MAP_WIDTH};
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use std::collections::HashSet;
+
+/// How many tiles of one kind of special a chunk gets, and how far apart
+#[derive(Debug, Clone, Copy)]
+pub struct Quota {
+    pub interaction: TileInteraction,
+    /// Fewest tiles of this kind in the origin chunk
+    pub min: usize,
+    /// Most tiles of this kind in the origin chunk
+    pub max: usize,
+    /// Rings away from the origin it takes for `min` and `max` to go up by one,
+    /// 0 if they stay the same everywhere
+    pub rings_per_step: usize,
+    /// Most tiles of this kind in a chunk, however far out it is
+    pub cap: usize,
+    /// Fewest steps from one tile of this kind to the next, diagonal steps included,
+    /// so 2 keeps them from touching
+    pub spacing: usize,
+}
+
+/// Special tiles get balanced in this order, so earlier kinds get the first pick of free spots
+pub const QUOTAS: [Quota; 3] = [
+    // Always a way to patch the party up, but never a whole field of hearts
+    Quota {
+        interaction: TileInteraction::Heal,
+        min: 1,
+        max: 2,
+        rings_per_step: 0,
+        cap: 2,
+        spacing: 6,
+    },
+    // More monsters farther out, but never side by side
+    Quota {
+        interaction: TileInteraction::Monster,
+        min: 1,
+        max: 3,
+        rings_per_step: 3,
+        cap: 8,
+        spacing: 2,
+    },
+    Quota {
+        interaction: TileInteraction::Chest,
+        min: 0,
+        max: 1,
+        rings_per_step: 5,
+        cap: 3,
+        spacing: 5,
+    },
+];
+
+impl Quota {
+    /// Fewest and most tiles of this kind in a chunk `ring` steps from the origin
+    pub fn limits(&self, ring: usize) -> (usize, usize) {
+        let steps = ring.checked_div(self.rings_per_step).unwrap_or(0);
+        let max = (self.max + steps).min(self.cap);
+        ((self.min + steps).min(max), max)
+    }
+}
+
+/// Add and remove special tiles until every kind in `QUOTAS` is within its limits for a chunk
+/// `ring` steps from the origin, and no two tiles of a kind are closer than its spacing.
+///
+/// Removed specials become `floor`, and new ones only go on `floor` tiles, so nothing that was
+/// walkable stops being walkable. Cells in `fixed` are never changed but still count, so
+/// passing the border cells that line up with a neighbor keeps the seam between them intact.
+///
+/// Returns the number of tiles that were changed.
+pub fn balance(
+    tiles: &mut [Vec<usize>],
+    ring: usize,
+    rng: &mut StdRng,
+    registry: &TileRegistry,
+    fixed: &HashSet<(usize, usize)>,
+    floor: usize,
+) -> usize {
+    let mut changed = 0;
+    for quota in QUOTAS {
+        // Tile to place for this kind, even if the biome's inputs never have it
+        let Some(special) = registry
+            .tiles
+            .iter()
+            .filter(|(_, info)| info.interaction == quota.interaction)
+            .map(|(t, _)| *t)
+            .min()
+        else {
+            continue;
+        };
+        let (min, max) = quota.limits(ring);
+        let far_enough = |kept: &[(usize, usize)], (row, col): (usize, usize)| {
+            kept.iter()
+                .all(|&(r, c)| row.abs_diff(r).max(col.abs_diff(c)) >= quota.spacing)
+        };
+
+        // Fixed ones stay no matter what, the rest get kept in random order while they fit
+        let (mut kept, mut loose): (Vec<_>, Vec<_>) =
+            cells_where(tiles, |t| registry.interaction(t) == quota.interaction)
+                .into_iter()
+                .partition(|cell| fixed.contains(cell));
+        loose.shuffle(rng);
+        for cell in loose {
+            if kept.len() < max && far_enough(&kept, cell) {
+                kept.push(cell);
+            } else {
+                tiles[cell.0][cell.1] = floor;
+                changed += 1;
+            }
+        }
+
+        let mut free = cells_where(tiles, |t| t == floor);
+        free.retain(|cell| !fixed.contains(cell));
+        free.shuffle(rng);
+        for cell in free {
+            if kept.len() >= min {
+                break;
+            }
+            if far_enough(&kept, cell) {
+                tiles[cell.0][cell.1] = special;
+                kept.push(cell);
+                changed += 1;
+            }
+        }
+    }
+    changed
+}
+
+/// (row, column) of every cell whose tile `matches`, in row major order
+fn cells_where(tiles: &[Vec<usize>], matches: impl Fn(usize) -> bool) -> Vec<(usize, usize)> {
+    (0..MAP_HEIGHT)
+        .flat_map(|row| (0..MAP_WIDTH).map(move |col| (row, col)))
+        .filter(|&(row, col)| matches(tiles[row][col]))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::SeedableRng;
+
+    const FLOOR: usize = 0;
+    const HEART: usize = 1;
+    const MONSTER: usize = 2;
+
+    fn quota(interaction: TileInteraction) -> Quota {
+        QUOTAS
+            .into_iter()
+            .find(|quota| quota.interaction == interaction)
+            .unwrap()
+    }
+
+    #[test]
+    fn limits_grow_with_the_ring_up_to_the_cap() {
+        let monster = quota(TileInteraction::Monster);
+        assert_eq!(monster.limits(0), (1, 3));
+        assert_eq!(monster.limits(2), (1, 3));
+        assert_eq!(monster.limits(3), (2, 4));
+        assert_eq!(monster.limits(300), (monster.cap, monster.cap));
+        // No steps means the same limits everywhere
+        let heal = quota(TileInteraction::Heal);
+        assert_eq!(heal.limits(300), heal.limits(0));
+    }
+
+    #[test]
+    fn balance_keeps_specials_within_quota_and_apart() {
+        let registry = TileRegistry::parse(
+            "sheet tiles.png 4 1\n\
+             0 floor walkable none 100 -\n\
+             1 heart walkable heal 100 -\n\
+             2 monster walkable monster 100 -\n\
+             3 chest walkable chest 100 -\n",
+        )
+        .unwrap();
+        let heal = quota(TileInteraction::Heal);
+        let monster = quota(TileInteraction::Monster);
+        // A heart on the border that has to stay, and a pile of hearts and monsters next to it
+        let fixed = HashSet::from([(0, 0)]);
+        let mut start = vec![vec![FLOOR; MAP_WIDTH]; MAP_HEIGHT];
+        start[0][0] = HEART;
+        start[1][1..6].fill(HEART);
+        start[2][1..6].fill(MONSTER);
+
+        for seed in 0..20 {
+            let mut tiles = start.clone();
+            let changed = balance(
+                &mut tiles,
+                0,
+                &mut StdRng::seed_from_u64(seed),
+                &registry,
+                &fixed,
+                FLOOR,
+            );
+
+            let differences = (0..MAP_HEIGHT)
+                .flat_map(|row| (0..MAP_WIDTH).map(move |col| (row, col)))
+                .filter(|&(row, col)| tiles[row][col] != start[row][col])
+                .count();
+            assert_eq!(changed, differences);
+            assert_eq!(tiles[0][0], HEART);
+            for (quota, special) in [(heal, HEART), (monster, MONSTER)] {
+                let cells = cells_where(&tiles, |t| t == special);
+                let (min, max) = quota.limits(0);
+                assert!((min..=max).contains(&cells.len()), "seed {}", seed);
+                for (i, &(row, col)) in cells.iter().enumerate() {
+                    for &(r, c) in &cells[i + 1..] {
+                        assert!(
+                            row.abs_diff(r).max(col.abs_diff(c)) >= quota.spacing,
+                            "seed {}: tile {} at ({}, {}) and ({}, {})",
+                            seed,
+                            special,
+                            row,
+                            col,
+                            r,
+                            c
+                        );
+                    }
+                }
+            }
+        }
+    }
+}
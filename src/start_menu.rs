@@ -2,6 +2,7 @@
 use crate::backgrounds::{Tile, WIN_H, WIN_W};
 use crate::camera::MenuCamera;
 use crate::game_client::*;
+use crate::layout::{vh, vw};
 use crate::player::Player;
 use crate::{battle, GameState};
 use bevy::{prelude::*, ui::*};
@@ -258,7 +259,7 @@ fn setup_menu(
     commands
         .spawn_bundle(ButtonBundle {
             style: Style {
-                size: Size::new(Val::Px(300.0), Val::Px(65.0)),
+                size: Size::new(vw(300.0), vh(65.0)),
                 // center button
                 margin: UiRect::all(Val::Auto),
                 // horizontally center child text
@@ -287,7 +288,7 @@ fn setup_menu(
     commands
         .spawn_bundle(ButtonBundle {
             style: Style {
-                size: Size::new(Val::Px(325.0), Val::Px(65.0)),
+                size: Size::new(vw(325.0), vh(65.0)),
                 // center button
                 margin: UiRect::all(Val::Auto),
                 // horizontally center child text
@@ -296,8 +297,8 @@ fn setup_menu(
                 align_items: AlignItems::Center,
                 position_type: PositionType::Absolute,
                 position: UiRect {
-                    bottom: Val::Px(275.),
-                    left: Val::Px((WIN_W * 0.75) / 2.),
+                    bottom: vh(275.),
+                    left: vw((WIN_W * 0.75) / 2.),
                     ..default()
                 },
                 ..default()
@@ -322,7 +323,7 @@ fn setup_menu(
     commands
         .spawn_bundle(ButtonBundle {
             style: Style {
-                size: Size::new(Val::Px(225.0), Val::Px(65.0)),
+                size: Size::new(vw(225.0), vh(65.0)),
                 // center button
                 margin: UiRect::all(Val::Auto),
                 // horizontally center child text
@@ -331,8 +332,8 @@ fn setup_menu(
                 align_items: AlignItems::Center,
                 position_type: PositionType::Absolute,
                 position: UiRect {
-                    bottom: Val::Px(200.),
-                    left: Val::Px((WIN_W * 0.825) / 2.),
+                    bottom: vh(200.),
+                    left: vw((WIN_W * 0.825) / 2.),
                     ..default()
                 },
                 ..default()
@@ -357,7 +358,7 @@ fn setup_menu(
     commands
         .spawn_bundle(ButtonBundle {
             style: Style {
-                size: Size::new(Val::Px(125.0), Val::Px(65.0)),
+                size: Size::new(vw(125.0), vh(65.0)),
                 // center button
                 margin: UiRect::all(Val::Auto),
                 // horizontally center child text
@@ -366,8 +367,8 @@ fn setup_menu(
                 align_items: AlignItems::Center,
                 position_type: PositionType::Absolute,
                 position: UiRect {
-                    bottom: Val::Px(120.),
-                    left: Val::Px((WIN_W * 0.900) / 2.),
+                    bottom: vh(120.),
+                    left: vw((WIN_W * 0.900) / 2.),
                     ..default()
                 },
                 ..default()
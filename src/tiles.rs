@@ -0,0 +1,357 @@
+use log::warn;
+use std::collections::HashMap;
+use std::fmt;
+use std::fs::read_to_string;
+use std::path::Path;
+
+/// Tile registry describing every tile of the overworld tilesheet, relative to the assets folder
+pub const TILE_REGISTRY: &str = "backgrounds/overworld_tiles.txt";
+/// Copy of `TILE_REGISTRY` built into the game, for when the one in the assets folder is broken
+const BUILT_IN_REGISTRY: &str = include_str!("../assets/backgrounds/overworld_tiles.txt");
+/// Spawn weight that keeps a tile as frequent as it is in the WFC inputs
+pub const DEFAULT_SPAWN_WEIGHT: usize = 100;
+/// Seconds each frame of an animated tile shows for, unless the registry says otherwise
+pub const DEFAULT_FRAME_TIME: f32 = 0.2;
+
+/// What happens when the player steps on a tile
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TileInteraction {
+    None,
+    /// Starts a battle
+    Monster,
+    /// Restores the party's health
+    Heal,
+    /// Gives the player a random item
+    Chest,
+    /// Gives the player a quest
+    Npc,
+}
+
+/// Ways a tile can be turned around when learning rules from sample boards
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TileTransform {
+    /// Mirror left to right
+    MirrorX,
+    /// Mirror top to bottom
+    MirrorY,
+    /// Clockwise quarter turn
+    Rotate,
+}
+
+impl TileTransform {
+    const ALL: [TileTransform; 3] = [
+        TileTransform::MirrorX,
+        TileTransform::MirrorY,
+        TileTransform::Rotate,
+    ];
+
+    fn name(&self) -> &'static str {
+        match self {
+            TileTransform::MirrorX => "mirror-x",
+            TileTransform::MirrorY => "mirror-y",
+            TileTransform::Rotate => "rotate",
+        }
+    }
+}
+
+/// Everything the game needs to know about one atlas index
+#[derive(Debug, Clone)]
+pub struct TileInfo {
+    pub name: String,
+    pub walkable: bool,
+    pub interaction: TileInteraction,
+    /// How often WFC places this tile, in percent of how often it shows up in the inputs
+    pub spawn_weight: usize,
+    /// Atlas indexes to cycle through, empty if the tile isn't animated
+    pub frames: Vec<usize>,
+    /// Seconds each frame shows for
+    pub frame_time: f32,
+    /// Whether the tile sticks up off the ground, so it gets drawn on the decoration layer
+    /// and things standing behind it go behind it
+    pub decoration: bool,
+    /// What the tile looks like after each `TileTransform`, in `TileTransform::ALL` order.
+    /// `None` if no tile in the sheet looks like that.
+    pub symmetry: [Option<usize>; 3],
+}
+
+/// Tile registry, loaded from `TILE_REGISTRY` in the assets folder.
+///
+/// The file has a `sheet <path> <columns> <rows>` line for the tilesheet, followed by
+/// one `<index> <name> <walkable> <interaction> <spawn weight> <frames> [symmetry]` line per tile.
+/// Frames are comma separated atlas indexes, optionally followed by `@<seconds>` per frame,
+/// or `-` for none. Symmetry is a comma separated
+/// list of `<transform>:<index>` for the tile a transform turns this one into, just `<transform>`
+/// if it stays the same, or `all` if every transform leaves it the same.
+/// `decoration <index>...` lines list the tiles that go on the decoration layer.
+/// `#` starts a comment.
+#[derive(Debug, Clone)]
+pub struct TileRegistry {
+    /// Path of the tilesheet, relative to the assets folder
+    pub sheet: String,
+    pub columns: usize,
+    pub rows: usize,
+    pub tiles: HashMap<usize, TileInfo>,
+}
+
+/// Problem found while reading the tile registry
+#[derive(Debug)]
+pub struct TileRegistryError {
+    pub line: usize,
+    pub message: String,
+}
+
+impl fmt::Display for TileRegistryError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+impl Default for TileRegistry {
+    /// Load `TILE_REGISTRY` from the assets folder, or the built-in copy if that fails
+    fn default() -> Self {
+        Self::load(&Path::new("assets").join(TILE_REGISTRY)).unwrap_or_else(|e| {
+            warn!("{}, using the built-in tile registry instead", e);
+            Self::parse(BUILT_IN_REGISTRY).expect("built-in tile registry should parse")
+        })
+    }
+}
+
+impl TileRegistry {
+    /// Read and parse the tile registry at `path`.
+    ///
+    /// The error says what went wrong, and on which line of the file if it was there.
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let contents =
+            read_to_string(path).map_err(|e| format!("Couldn't read {}: {}", path.display(), e))?;
+        Self::parse(&contents).map_err(|e| format!("{}: {}", path.display(), e))
+    }
+
+    pub fn parse(contents: &str) -> Result<Self, TileRegistryError> {
+        let mut sheet: Option<(String, usize, usize)> = None;
+        let mut tiles: HashMap<usize, TileInfo> = HashMap::new();
+        // Tiles that symmetries turn into, checked once every tile is known
+        let mut targets: Vec<(usize, usize)> = Vec::new();
+        // Same for the tiles on decoration lines
+        let mut decorations: Vec<(usize, usize)> = Vec::new();
+
+        for (i, line) in contents.lines().enumerate() {
+            let line_no = i + 1;
+            let err = |message: String| TileRegistryError {
+                line: line_no,
+                message,
+            };
+            let fields: Vec<&str> = line
+                .split('#')
+                .next()
+                .unwrap_or("")
+                .split_whitespace()
+                .collect();
+            if fields.is_empty() {
+                continue;
+            }
+
+            if fields[0] == "sheet" {
+                if fields.len() != 4 {
+                    return Err(err("expected `sheet <path> <columns> <rows>`".to_string()));
+                }
+                let columns = parse_number(fields[2]).map_err(err)?;
+                let rows = parse_number(fields[3]).map_err(err)?;
+                sheet = Some((fields[1].to_string(), columns, rows));
+                continue;
+            }
+
+            if fields[0] == "decoration" {
+                for field in &fields[1..] {
+                    decorations.push((line_no, parse_number(field).map_err(err)?));
+                }
+                continue;
+            }
+
+            let (_, columns, rows) = sheet
+                .as_ref()
+                .ok_or_else(|| err("tiles listed before the `sheet` line".to_string()))?;
+            let in_sheet = |index: usize| {
+                if index < columns * rows {
+                    Ok(index)
+                } else {
+                    Err(err(format!(
+                        "atlas index {} is outside the {}x{} sheet",
+                        index, columns, rows
+                    )))
+                }
+            };
+
+            if fields.len() != 6 && fields.len() != 7 {
+                return Err(err(format!(
+                    "expected 6 or 7 fields for a tile, found {}",
+                    fields.len()
+                )));
+            }
+            let index = in_sheet(parse_number(fields[0]).map_err(err)?)?;
+            let walkable = match fields[2] {
+                "walkable" => true,
+                "solid" => false,
+                other => {
+                    return Err(err(format!(
+                        "expected `walkable` or `solid`, found `{}`",
+                        other
+                    )))
+                }
+            };
+            let interaction = match fields[3] {
+                "none" => TileInteraction::None,
+                "monster" => TileInteraction::Monster,
+                "heal" => TileInteraction::Heal,
+                "chest" => TileInteraction::Chest,
+                "npc" => TileInteraction::Npc,
+                other => return Err(err(format!("unknown interaction `{}`", other))),
+            };
+            let spawn_weight = parse_number(fields[4]).map_err(err)?;
+            let (frames, frame_time) = match fields[5].split_once('@') {
+                Some((frames, seconds)) => match seconds.parse::<f32>() {
+                    Ok(frame_time) if frame_time > 0. => (frames, frame_time),
+                    _ => {
+                        return Err(err(format!(
+                            "expected seconds per frame, found `{}`",
+                            seconds
+                        )))
+                    }
+                },
+                None => (fields[5], DEFAULT_FRAME_TIME),
+            };
+            let frames = if frames == "-" {
+                Vec::new()
+            } else {
+                frames
+                    .split(',')
+                    .map(|frame| in_sheet(parse_number(frame).map_err(err)?))
+                    .collect::<Result<Vec<usize>, TileRegistryError>>()?
+            };
+
+            let mut symmetry = [None; 3];
+            match fields.get(6) {
+                None => {}
+                Some(&"all") => symmetry = [Some(index); 3],
+                Some(list) => {
+                    for entry in list.split(',') {
+                        let (name, target) = entry.split_once(':').unwrap_or((entry, ""));
+                        let i = TileTransform::ALL
+                            .iter()
+                            .position(|t| t.name() == name)
+                            .ok_or_else(|| err(format!("unknown transform `{}`", name)))?;
+                        let target = if target.is_empty() {
+                            index
+                        } else {
+                            in_sheet(parse_number(target).map_err(err)?)?
+                        };
+                        symmetry[i] = Some(target);
+                        targets.push((line_no, target));
+                    }
+                }
+            }
+
+            let info = TileInfo {
+                name: fields[1].to_string(),
+                walkable,
+                interaction,
+                spawn_weight,
+                frames,
+                frame_time,
+                decoration: false,
+                symmetry,
+            };
+            if let Some(previous) = tiles.insert(index, info) {
+                return Err(err(format!(
+                    "tile {} is already registered as `{}`",
+                    index, previous.name
+                )));
+            }
+        }
+
+        if let Some((line, target)) = targets.iter().find(|(_, t)| !tiles.contains_key(t)) {
+            return Err(TileRegistryError {
+                line: *line,
+                message: format!(
+                    "symmetry turns into tile {}, which isn't registered",
+                    target
+                ),
+            });
+        }
+
+        for (line, index) in decorations {
+            match tiles.get_mut(&index) {
+                Some(info) => info.decoration = true,
+                None => {
+                    return Err(TileRegistryError {
+                        line,
+                        message: format!("decoration tile {} isn't registered", index),
+                    })
+                }
+            }
+        }
+
+        let (sheet, columns, rows) = sheet.ok_or(TileRegistryError {
+            line: contents.lines().count(),
+            message: "missing the `sheet` line".to_string(),
+        })?;
+        Ok(Self {
+            sheet,
+            columns,
+            rows,
+            tiles,
+        })
+    }
+
+    pub fn get(&self, index: usize) -> Option<&TileInfo> {
+        self.tiles.get(&index)
+    }
+
+    /// Check if the player can walk over the tile, tiles missing from the registry are walkable
+    pub fn walkable(&self, index: usize) -> bool {
+        self.get(index).is_none_or(|info| info.walkable)
+    }
+
+    /// What the tile looks like after `transform`, if any tile in the sheet does
+    pub fn transformed(&self, index: usize, transform: TileTransform) -> Option<usize> {
+        self.get(index)
+            .and_then(|info| info.symmetry[transform as usize])
+    }
+
+    /// What stepping on the tile does, tiles missing from the registry do nothing
+    pub fn interaction(&self, index: usize) -> TileInteraction {
+        self.get(index)
+            .map_or(TileInteraction::None, |info| info.interaction)
+    }
+}
+
+fn parse_number(field: &str) -> Result<usize, String> {
+    field
+        .parse::<usize>()
+        .map_err(|_| format!("expected a number, found `{}`", field))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn built_in_registry_parses() {
+        let registry = TileRegistry::parse(BUILT_IN_REGISTRY).unwrap();
+        assert!(!registry.tiles.is_empty());
+    }
+
+    #[test]
+    fn reports_the_line_of_a_bad_tile() {
+        let e = TileRegistry::parse(
+            "sheet tiles.png 2 2\n\n0 sand walkable none 100 -\n1 wall maybe none 100 -\n",
+        )
+        .unwrap_err();
+        assert_eq!(e.line, 4);
+    }
+
+    #[test]
+    fn load_says_which_file_failed() {
+        let e = TileRegistry::load(Path::new("no/such/registry.txt")).unwrap_err();
+        assert!(e.contains("no/such/registry.txt"), "{}", e);
+    }
+}
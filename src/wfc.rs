@@ -1,109 +1,774 @@
-use rand::distributions::WeightedIndex;
-use rand::prelude::Distribution;
-//use colored::Colorize;
+use rand::rngs::StdRng;
 use rand::seq::SliceRandom;
-use std::collections::HashMap;
+use rand::{Rng, SeedableRng};
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::env;
+use std::fmt;
+use std::fs::{read_dir, read_to_string};
 use std::hash::Hash;
-// use std::env;
-use std::ops::Index;
-use std::ops::IndexMut;
-use std::thread;
-use std::{fs::read_dir, fs::File, io::Read};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 
 use rand::thread_rng;
 
-use crate::backgrounds::{
-    // CHUNK_HEIGHT, CHUNK_WIDTH,
-    MAP_HEIGHT,
-    MAP_WIDTH,
-};
+use crate::biomes::{biome_at, Biome};
+use crate::connectivity::{connect, reachability};
+use crate::prefabs::{read_prefabs, Prefab, PREFABS};
+use crate::seams::Seams;
+use crate::specials::balance;
+use crate::tiles::{TileInteraction, TileRegistry, TileTransform, DEFAULT_SPAWN_WEIGHT};
+use crate::wfc_input::{WfcInput, WfcModel};
+use log::{error, warn};
+
+/// Width of a chunk in tiles
+pub const MAP_WIDTH: usize = 20;
+/// Height of a chunk in tiles
+pub const MAP_HEIGHT: usize = 12;
 
 const MAX_REPICK_ATTEMPTS: usize = 20;
+/// Number of choices one attempt may undo before starting over
+const MAX_BACKTRACKS: usize = 2000;
+/// Cells one `wfc()` run may collapse, across all of its attempts
+const MAX_STEPS: usize = 20_000;
+/// Times one `wfc()` run may update a cell's neighbors while propagating, across all of its attempts
+const MAX_PROPAGATIONS: usize = 200_000;
+/// Times a chunk with unreachable tiles gets generated again before paths are cut through it
+const MAX_REGENERATIONS: usize = 3;
+
+/// Folder the WFC sample boards are read from
+pub const WFC_INPUTS: &str = "assets/backgrounds/wfc_inputs";
+
+/// Environment variable that can be set to a number to force the world seed
+pub const SEED_VAR: &str = "WASTE_SEED";
 
 /// Resource to hold game-wide memory-resident information for procedural generation
-pub(crate) struct ProcGen {
-    /// Rules generated by initial rule generation
-    pub(crate) rules: HashMap<usize, Rule>,
+///
+/// Cloning is cheap, so chunk generation tasks can take their own copy.
+#[derive(Clone)]
+pub struct ProcGen {
+    /// Rules generated by initial rule generation, for each biome
+    pub rules: Arc<HashMap<Biome, HashMap<usize, Rule>>>,
+    /// Patterns of the biomes generated with the overlapping model,
+    /// the rest use the tiled model
+    pub patterns: Arc<HashMap<Biome, Patterns>>,
+    /// Prefabs to stamp into chunks, by logical position
+    pub landmarks: Arc<HashMap<(isize, isize), Landmark>>,
+    /// What every tile in the tilesheet is and does
+    pub tiles: Arc<TileRegistry>,
     // /// Tile frequencies generated by initial rule generation
-    // pub(crate) freqs: HashMap<usize, usize>
+    // pub freqs: HashMap<usize, usize>
+    /// World seed that every chunk's random number generator is derived from
+    pub seed: u64,
+    /// How much work generating one chunk may take
+    pub budget: WfcBudget,
 }
 
 impl Default for ProcGen {
     fn default() -> Self {
-        Self {
-            rules: init_rules(),
+        let (procgen, skipped) = Self::new(Path::new(WFC_INPUTS), Path::new(PREFABS), init_seed());
+        for problem in skipped {
+            warn!("{}", problem);
         }
+        procgen
     }
 }
 
-/// Initialize rules by reading every input file in the directory
-/// 'assets/backgrounds/wfc_inpits/' and running rulegen on them.
-pub(crate) fn init_rules() -> HashMap<usize, Rule> {
-    let all_inputs = read_dir("assets/backgrounds/wfc_inputs").unwrap();
-    let mut freqs: HashMap<usize, usize> = HashMap::new();
-    let mut rules: HashMap<usize, Rule> = HashMap::new();
+impl ProcGen {
+    /// Learn rules from the WFC inputs under `dir` and read the prefabs under `prefabs`
+    /// for a world with the given seed.
+    ///
+    /// Also returns why each input file or prefab that couldn't be used was skipped.
+    pub fn new(dir: &Path, prefabs: &Path, seed: u64) -> (Self, Vec<String>) {
+        let tiles = TileRegistry::default();
+        let (inputs, mut skipped) = read_inputs(dir, &tiles);
+        let (prefabs, skipped_prefabs) = read_prefabs(prefabs, &tiles);
+        skipped.extend(skipped_prefabs);
+        let procgen = Self {
+            rules: Arc::new(init_rules(&inputs, &tiles)),
+            patterns: Arc::new(init_patterns(&inputs, &tiles)),
+            landmarks: Arc::new(init_landmarks(prefabs, &mut skipped)),
+            tiles: Arc::new(tiles),
+            seed,
+            budget: WfcBudget::default(),
+        };
+        (procgen, skipped)
+    }
 
-    // Check all inputs
-    for input_file in all_inputs {
-        // Run rulegen on the input file given
-        rulegen(
-            input_file.unwrap().path().to_str().unwrap(),
-            &mut rules,
-            &mut freqs,
+    /// Generate the tiles of the chunk at logical position (x, y).
+    ///
+    /// The result only depends on the world seed and the chunk's position, so chunks can be
+    /// generated in any order. Its border comes from `seams()`, which picks it together
+    /// with the borders of the neighbors facing it.
+    ///
+    /// Special tiles get evened out afterwards, so every chunk has some but not too many.
+    /// That leaves the border alone wherever it lines up with a neighbor.
+    pub fn generate(&self, x: isize, y: isize) -> ChunkLayout {
+        let mut rng = chunk_rng(self.seed, x, y);
+        let mut seams = self.seams(x, y);
+        let mut layout = self.connected_layout(&mut seams, x, y, &mut rng);
+        balance(
+            &mut layout.tiles,
+            ring(x, y).unsigned_abs(),
+            &mut rng,
+            &self.tiles,
+            &self.fixed_cells(x, y, &seams, layout.filled),
+            layout.floor,
         );
+        layout
     }
 
-    rules
+    /// Generate the chunk at (x, y) until everything walkable in it can be reached.
+    ///
+    /// Chunks where some walkable tiles can't be reached, or that can't be walked into
+    /// from a neighbor, get generated again. If that keeps happening, paths get cut through them.
+    ///
+    /// Sides the layout it settled on had to leave out are taken out of `seams`, and only the
+    /// neighbors it still lines up with have to be reachable.
+    fn connected_layout(
+        &self,
+        seams: &mut Seams,
+        x: isize,
+        y: isize,
+        rng: &mut StdRng,
+    ) -> ChunkLayout {
+        let mut stats = WfcStats::default();
+        let mut regenerations = 0;
+        loop {
+            let mut held = seams.clone();
+            let mut layout = self.layout(&mut held, x, y, rng);
+            stats.add(&layout.stats);
+            layout.regenerations = regenerations;
+            if reachability(&layout.tiles, &held.edges, &self.tiles).is_connected() {
+                layout.stats = stats;
+                *seams = held;
+                return layout;
+            }
+            // A filled in chunk would come out the same every time
+            if regenerations < MAX_REGENERATIONS && !layout.filled {
+                regenerations += 1;
+                continue;
+            }
+
+            let fixed = self.fixed_cells(x, y, &held, layout.filled);
+            layout.carved = connect(
+                &mut layout.tiles,
+                &held.edges,
+                &self.tiles,
+                &fixed,
+                layout.floor,
+            );
+            warn!(
+                "Chunk ({}, {}) still had unreachable tiles after {} regeneration(s), changed {} tile(s) to connect it",
+                x, y, regenerations, layout.carved
+            );
+            layout.stats = stats;
+            *seams = held;
+            return layout;
+        }
+    }
+
+    /// Generate the tiles of the chunk at (x, y) once, relaxing its edges as far as needed.
+    ///
+    /// First the whole border `seams()` picked is kept, leaving out the side closest to where each
+    /// attempt ran into a contradiction. Sides that had to be left out are taken out of `seams`.
+    /// Once every side is out, the neighbors' borders are relaxed like `ChunkEdges::fallbacks()` says,
+    /// and `seams` keeps the ones that were still used.
+    fn layout(&self, seams: &mut Seams, x: isize, y: isize, rng: &mut StdRng) -> ChunkLayout {
+        let (biome, rules) = self.chunk_rules(x, y);
+        let floor = floor_tile(&rules, &self.tiles);
+        let patterns = self.chunk_patterns(x, y);
+        let landmark = self.landmarks.get(&(x, y));
+        let landmark_seeding = landmark.map_or(Vec::new(), |landmark| landmark.seeding.clone());
+        let mut stats = WfcStats::default();
+
+        let mut attempt =
+            |seeding: Vec<(usize, (usize, usize))>, edges: &ChunkEdges, stats: &mut WfcStats| {
+                let seeding = Some(seeding);
+                let generated = match patterns {
+                    // Whole patterns are harder to fit against the neighbors than single tiles,
+                    // so try the tiled model before relaxing anything
+                    Some(patterns) => {
+                        wfc_overlapping(seeding.clone(), edges, patterns, &rules, rng, self.budget)
+                            .or_else(|e| {
+                                warn!("Overlapping model failed on chunk ({}, {}): {}", x, y, e);
+                                stats.add(e.stats());
+                                wfc(seeding.clone(), edges, &rules, rng, self.budget)
+                            })
+                    }
+                    None => wfc(seeding, edges, &rules, rng, self.budget),
+                };
+                generated.map_err(|e| {
+                    warn!("Generating chunk ({}, {}) failed: {}", x, y, e);
+                    stats.add(e.stats());
+                    e
+                })
+            };
+
+        let mut relaxations = 0;
+        let mut generated = None;
+        loop {
+            let mut seeding = landmark_seeding.clone();
+            seeding.extend(seams.seeding());
+            match attempt(seeding, &seams.edges, &mut stats) {
+                Ok(g) => {
+                    generated = Some(g);
+                    break;
+                }
+                Err(e) => {
+                    relaxations += 1;
+                    let cell = e.stats().last_contradiction.map(|c| c.cell);
+                    if !cell.is_some_and(|cell| seams.leave_out_nearest(cell)) {
+                        break;
+                    }
+                }
+            }
+        }
+        if generated.is_none() {
+            seams.sides = Default::default();
+            let edges = ChunkEdges {
+                border_tiles: self.border_tiles(x, y),
+                ..seams.edges.clone()
+            };
+            // Only the neighbors' borders it ends up generated against still line up with it
+            seams.edges = ChunkEdges::default();
+            for edges in edges.fallbacks() {
+                match attempt(landmark_seeding.clone(), &edges, &mut stats) {
+                    Ok(g) => {
+                        seams.edges = ChunkEdges {
+                            border_tiles: Default::default(),
+                            ..edges
+                        };
+                        generated = Some(g);
+                        break;
+                    }
+                    Err(_) => relaxations += 1,
+                }
+            }
+        }
+
+        if let Some(generated) = generated {
+            if relaxations > 0 {
+                warn!(
+                    "Chunk ({}, {}) could not match all of its neighbors, relaxed its edges {} time(s)",
+                    x, y, relaxations
+                );
+            }
+            stats.add(&generated.stats);
+            return ChunkLayout {
+                biome,
+                tiles: generated.tiles,
+                floor,
+                landmark: landmark.map(|landmark| landmark.name.clone()),
+                relaxations,
+                filled: false,
+                regenerations: 0,
+                carved: 0,
+                stats,
+            };
+        }
+
+        error!(
+            "Chunk ({}, {}) could not be generated even without constraints, filling it in",
+            x, y
+        );
+        // Landmarks still have to be there for the game to be beaten
+        let mut tiles = filled_chunk(&self.rules[&biome]);
+        for (t, (row, col)) in landmark_seeding {
+            tiles[row][col] = t;
+        }
+        ChunkLayout {
+            biome,
+            tiles,
+            floor,
+            landmark: landmark.map(|landmark| landmark.name.clone()),
+            relaxations,
+            filled: true,
+            regenerations: 0,
+            carved: 0,
+            stats,
+        }
+    }
+
+    /// Cells of the chunk at (x, y) that have to stay the way they were made: its landmark,
+    /// and the border on every side `seams` kept, which lines up with the neighbor across from it
+    fn fixed_cells(
+        &self,
+        x: isize,
+        y: isize,
+        seams: &Seams,
+        filled: bool,
+    ) -> HashSet<(usize, usize)> {
+        let mut fixed = self
+            .landmarks
+            .get(&(x, y))
+            .map_or(HashSet::new(), |landmark| {
+                landmark.seeding.iter().map(|(_, cell)| *cell).collect()
+            });
+        // A filled in chunk doesn't line up with anything anyway
+        if !filled {
+            fixed.extend(seams.seeding().iter().map(|(_, cell)| *cell));
+        }
+        fixed
+    }
+
+    /// Biomes whose tiles can show up in the chunk at (x, y): its own,
+    /// followed by any other biomes its neighbors are in.
+    fn chunk_biomes(&self, x: isize, y: isize) -> Vec<Biome> {
+        let mut biomes = vec![biome_at(self.seed, x, y)];
+        for (nx, ny) in [(x + 1, y), (x, y + 1), (x - 1, y), (x, y - 1)] {
+            let neighbor = biome_at(self.seed, nx, ny);
+            if !biomes.contains(&neighbor) {
+                biomes.push(neighbor);
+            }
+        }
+        biomes
+    }
+
+    /// Every tile type the chunk at (x, y) can be generated with
+    fn chunk_tile_types(&self, x: isize, y: isize) -> HashSet<usize> {
+        let mut types = self
+            .chunk_biomes(x, y)
+            .iter()
+            .flat_map(|b| self.rules[b].keys().copied())
+            .collect::<HashSet<usize>>();
+        if let Some(landmark) = self.landmarks.get(&(x, y)) {
+            types.extend(landmark.rules.keys().copied());
+        }
+        types
+    }
+
+    /// Get the biome of the chunk at (x, y) and the rules to generate it with.
+    ///
+    /// Chunks bordering another biome get that biome's rules mixed in,
+    /// so they can match the edges of neighbors from both sides.
+    /// Chunks with a landmark also get the rules of its prefab.
+    pub fn chunk_rules(&self, x: isize, y: isize) -> (Biome, HashMap<usize, Rule>) {
+        let biomes = self.chunk_biomes(x, y);
+        let mut rule_sets = biomes
+            .iter()
+            .map(|b| &self.rules[b])
+            .collect::<Vec<&HashMap<usize, Rule>>>();
+        if let Some(landmark) = self.landmarks.get(&(x, y)) {
+            rule_sets.push(&landmark.rules);
+        }
+        (biomes[0], merge_rules(&rule_sets))
+    }
+
+    /// Set up the first attempt `generate()` makes at the chunk at (x, y),
+    /// to be collapsed one step at a time
+    pub fn stepper(&self, x: isize, y: isize) -> WfcStepper {
+        let (_, rules) = self.chunk_rules(x, y);
+        let patterns = self.chunk_patterns(x, y);
+        let seams = self.seams(x, y);
+        let mut seeding = seams.seeding();
+        if let Some(landmark) = self.landmarks.get(&(x, y)) {
+            seeding.extend(landmark.seeding.iter().copied());
+        }
+        WfcStepper::new(
+            Some(seeding),
+            &seams.edges,
+            &rules,
+            patterns,
+            chunk_rng(self.seed, x, y),
+        )
+    }
+
+    /// Patterns to generate the chunk at (x, y) with, if its biome uses the overlapping model.
+    ///
+    /// Chunks on a biome border always use the tiled model, since only its rules can be mixed.
+    /// So do chunks with a landmark, whose prefab isn't in any of the patterns.
+    pub fn chunk_patterns(&self, x: isize, y: isize) -> Option<&Patterns> {
+        if self.landmarks.contains_key(&(x, y)) {
+            return None;
+        }
+        match self.chunk_biomes(x, y)[..] {
+            [biome] => self.patterns.get(&biome),
+            _ => None,
+        }
+    }
+
+    /// Tiles the chunk at (x, y) may put on each of its borders, in `Dir::ALL` order,
+    /// so the neighbor on that side has the rules to continue from them.
+    /// Sides that need no limits are `None`.
+    pub fn border_tiles(&self, x: isize, y: isize) -> [Option<Vec<usize>>; 4] {
+        let own = self.chunk_tile_types(x, y);
+        [(x - 1, y), (x, y + 1), (x + 1, y), (x, y - 1)].map(|(nx, ny)| {
+            let theirs = self.chunk_tile_types(nx, ny);
+            if own.is_subset(&theirs) {
+                return None;
+            }
+            let mut shared = own.intersection(&theirs).copied().collect::<Vec<usize>>();
+            shared.sort_unstable();
+            Some(shared)
+        })
+    }
+}
+
+/// Read the world seed from the `WASTE_SEED` environment variable,
+/// or pick a random one if it is unset or not a number.
+pub fn init_seed() -> u64 {
+    match env::var(SEED_VAR)
+        .ok()
+        .and_then(|s| s.trim().parse::<u64>().ok())
+    {
+        Some(seed) => seed,
+        None => thread_rng().gen(),
+    }
+}
+
+/// Mix the world seed with a chunk's logical position to get that chunk's own seed.
+///
+/// Uses the splitmix64 finalizer so neighboring chunks get unrelated seeds.
+pub fn chunk_seed(seed: u64, x: isize, y: isize) -> u64 {
+    let mut z = seed
+        ^ (x as u64).wrapping_mul(0x9E37_79B9_7F4A_7C15)
+        ^ (y as u64).wrapping_mul(0xC2B2_AE3D_27D4_EB4F);
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    z ^ (z >> 31)
+}
+
+/// Get the random number generator used to generate the chunk at logical position (x, y).
+///
+/// The same seed and position always give the same generator, no matter
+/// what order chunks are generated in.
+pub fn chunk_rng(seed: u64, x: isize, y: isize) -> StdRng {
+    StdRng::seed_from_u64(chunk_seed(seed, x, y))
+}
+
+/// Distance of a chunk from the origin chunk, counted in chunk steps
+pub fn ring(x: isize, y: isize) -> isize {
+    x.abs() + y.abs()
+}
+
+/// Read every input file under `dir` (normally 'assets/backgrounds/wfc_inputs/'),
+/// grouped by the biome they belong to.
+///
+/// Files that can't be read or parsed are skipped, and the reason why is
+/// returned alongside the inputs.
+pub fn read_inputs(
+    dir: &Path,
+    tiles: &TileRegistry,
+) -> (HashMap<Biome, Vec<WfcInput>>, Vec<String>) {
+    let mut inputs: HashMap<Biome, Vec<WfcInput>> = HashMap::new();
+    let mut skipped: Vec<String> = Vec::new();
+    let files = input_files(dir).unwrap_or_else(|e| {
+        skipped.push(format!(
+            "Couldn't read WFC inputs in {}: {}",
+            dir.display(),
+            e
+        ));
+        Vec::new()
+    });
+    for path in files {
+        let input = match read_to_string(&path) {
+            Ok(contents) => WfcInput::parse(&contents, tiles),
+            Err(e) => {
+                skipped.push(format!("Skipping WFC input {}: {}", path.display(), e));
+                continue;
+            }
+        };
+        match input {
+            Ok(input) => {
+                // Older files without a header get their biome from the folder they're in
+                let folder = path
+                    .parent()
+                    .and_then(|p| p.file_name())
+                    .and_then(|name| name.to_str());
+                let biome = input.biome.or_else(|| {
+                    Biome::ALL
+                        .iter()
+                        .copied()
+                        .find(|b| Some(b.name()) == folder)
+                });
+                match biome {
+                    Some(biome) => inputs.entry(biome).or_default().push(input),
+                    None => skipped.push(format!(
+                        "Skipping WFC input {}: no `biome` header and not in a biome folder",
+                        path.display()
+                    )),
+                }
+            }
+            Err(e) => skipped.push(format!("Skipping WFC input {}: {}", path.display(), e)),
+        }
+    }
+    (inputs, skipped)
+}
+
+/// Initialize every biome's rules by running rulegen on its inputs
+pub fn init_rules(
+    inputs: &HashMap<Biome, Vec<WfcInput>>,
+    tiles: &TileRegistry,
+) -> HashMap<Biome, HashMap<usize, Rule>> {
+    Biome::ALL
+        .iter()
+        .map(|biome| {
+            let mut freqs: HashMap<usize, usize> = HashMap::new();
+            let mut rules: HashMap<usize, Rule> = HashMap::new();
+            for input in inputs.get(biome).into_iter().flatten() {
+                // Run rulegen on each input given
+                rulegen(input, tiles, &mut rules, &mut freqs);
+            }
+            if rules.is_empty() {
+                warn!("No usable WFC inputs for the {} biome", biome.name());
+            }
+
+            // Scale how often each tile shows up in the inputs by its spawn weight
+            for (tile_type, rule) in rules.iter_mut() {
+                let spawn_weight = tiles
+                    .get(*tile_type)
+                    .map_or(DEFAULT_SPAWN_WEIGHT, |info| info.spawn_weight);
+                // Tiles only seen in mirrored or rotated boards count as seen once
+                let seen = freqs
+                    .get(tile_type)
+                    .copied()
+                    .unwrap_or(DEFAULT_SPAWN_WEIGHT);
+                rule.freq = (seen * spawn_weight / DEFAULT_SPAWN_WEIGHT).max(1);
+            }
+            (*biome, rules)
+        })
+        .collect()
+}
+
+/// Gather patterns for every biome with an input that asks for the overlapping model
+pub fn init_patterns(
+    inputs: &HashMap<Biome, Vec<WfcInput>>,
+    tiles: &TileRegistry,
+) -> HashMap<Biome, Patterns> {
+    let mut all = HashMap::new();
+    for biome in Biome::ALL {
+        let biome_inputs = match inputs.get(&biome) {
+            Some(biome_inputs) => biome_inputs,
+            None => continue,
+        };
+        let mut sizes = biome_inputs.iter().filter_map(|input| match input.model {
+            WfcModel::Overlapping(size) => Some(size),
+            WfcModel::Tiled => None,
+        });
+        let size = match sizes.next() {
+            Some(size) => size,
+            None => continue,
+        };
+        if sizes.any(|other| other != size) {
+            warn!(
+                "WFC inputs for the {} biome disagree on the pattern size, using {}",
+                biome.name(),
+                size
+            );
+        }
+
+        let patterns = patterngen(biome_inputs, size, tiles);
+        if patterns.patterns.is_empty() {
+            warn!(
+                "No board in the {} biome fits {}x{} patterns, using the tiled model",
+                biome.name(),
+                size,
+                size
+            );
+            continue;
+        }
+        all.insert(biome, patterns);
+    }
+    all
+}
+
+/// Prefab stamped into a chunk, with what WFC needs to fit the rest of the chunk around it
+#[derive(Debug, Clone)]
+pub struct Landmark {
+    pub name: String,
+    /// Tiles of the prefab as (tile, (row, column)), in `wfc()` seeding form
+    pub seeding: Vec<(usize, (usize, usize))>,
+    /// Adjacencies seen on the prefab board, so its tiles can sit next to each other
+    /// even where no WFC input has them side by side
+    rules: HashMap<usize, Rule>,
+}
+
+/// Place every prefab at its positions, skipping positions another prefab already took
+pub fn init_landmarks(
+    prefabs: Vec<Prefab>,
+    skipped: &mut Vec<String>,
+) -> HashMap<(isize, isize), Landmark> {
+    let mut landmarks: HashMap<(isize, isize), Landmark> = HashMap::new();
+    for prefab in prefabs {
+        let mut rules = HashMap::new();
+        learn_neighbors(&prefab.board, &mut rules);
+        // Prefab tiles shouldn't show up anywhere else in the chunk more than the biome has them
+        for rule in rules.values_mut() {
+            rule.freq = 1;
+        }
+        let landmark = Landmark {
+            name: prefab.name.clone(),
+            seeding: prefab.seeding(),
+            rules,
+        };
+        for position in &prefab.positions {
+            if let Some(other) = landmarks.get(position) {
+                skipped.push(format!(
+                    "Skipping landmark {} at {:?}, {} is already there",
+                    prefab.name, position, other.name
+                ));
+                continue;
+            }
+            landmarks.insert(*position, landmark.clone());
+        }
+    }
+    landmarks
+}
+
+/// Every file under `dir`, sorted so the rules come out the same
+/// for the same world seed everywhere
+pub fn input_files(dir: &Path) -> io::Result<Vec<PathBuf>> {
+    let mut files = Vec::new();
+    // Directory order is up to the OS
+    let mut paths = read_dir(dir)?
+        .filter_map(|entry| entry.ok().map(|e| e.path()))
+        .collect::<Vec<_>>();
+    paths.sort();
+    for path in paths {
+        if path.is_dir() {
+            files.extend(input_files(&path)?);
+        } else {
+            files.push(path);
+        }
+    }
+    Ok(files)
+}
+
+/// Combine the rules of several biomes, allowing any tile next to another
+/// that either biome allows.
+pub fn merge_rules(rule_sets: &[&HashMap<usize, Rule>]) -> HashMap<usize, Rule> {
+    let mut merged: HashMap<usize, Rule> = HashMap::new();
+    for rules in rule_sets {
+        for (tile_type, rule) in rules.iter() {
+            match merged.get_mut(tile_type) {
+                None => {
+                    merged.insert(*tile_type, rule.clone());
+                }
+                Some(existing) => {
+                    existing.freq += rule.freq;
+                    for (dir, allowed) in rule.neighbor_rules.iter() {
+                        let existing_allowed = existing.neighbor_rules.entry(*dir).or_default();
+                        for t in allowed {
+                            if !existing_allowed.contains(t) {
+                                existing_allowed.push(*t);
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
+    merged
+}
+
+/// The input's board, followed by a copy for every mirror and rotation its symmetry
+/// flags allow. Cells whose tile has no counterpart in a copy are `None`.
+fn augmented_boards(input: &WfcInput, tiles: &TileRegistry) -> Vec<Vec<Vec<Option<usize>>>> {
+    let mut boards = vec![input
+        .board
+        .iter()
+        .map(|row| row.iter().map(|t| Some(*t)).collect())
+        .collect::<Vec<Vec<Option<usize>>>>()];
+
+    let symmetry = input.symmetry;
+    for (enabled, transform, times) in [
+        (symmetry.mirror_x, TileTransform::MirrorX, 1),
+        (symmetry.mirror_y, TileTransform::MirrorY, 1),
+        (symmetry.rotate, TileTransform::Rotate, 3),
+    ] {
+        if !enabled {
+            continue;
+        }
+        let mut copies = Vec::new();
+        for board in &boards {
+            let mut copy = board.clone();
+            for _ in 0..times {
+                copy = transform_board(&copy, transform, tiles);
+                copies.push(copy.clone());
+            }
+        }
+        boards.extend(copies);
+    }
+
+    boards
 }
+
+/// Mirror or rotate a board, swapping every tile for what it looks like afterwards
+fn transform_board(
+    board: &[Vec<Option<usize>>],
+    transform: TileTransform,
+    tiles: &TileRegistry,
+) -> Vec<Vec<Option<usize>>> {
+    let turned = |t: &Option<usize>| t.and_then(|t| tiles.transformed(t, transform));
+    match transform {
+        TileTransform::MirrorX => board
+            .iter()
+            .map(|row| row.iter().rev().map(turned).collect())
+            .collect(),
+        TileTransform::MirrorY => board
+            .iter()
+            .rev()
+            .map(|row| row.iter().map(turned).collect())
+            .collect(),
+        // A clockwise quarter turn, the left column becomes the top row
+        TileTransform::Rotate => (0..board[0].len())
+            .map(|col| board.iter().rev().map(|row| turned(&row[col])).collect())
+            .collect(),
+    }
+}
+
 /// Apply rulegeneration to given rule and frequency set based on input read from infile
-pub(crate) fn rulegen(
-    infile: &str,
+pub fn rulegen(
+    input: &WfcInput,
+    tiles: &TileRegistry,
     rules: &mut HashMap<usize, Rule>,
     freqs: &mut HashMap<usize, usize>,
 ) {
-    let mut file = File::open(infile).unwrap();
-    let mut contents = String::new();
-    file.read_to_string(&mut contents).unwrap();
-
-    // Board input is a text file of usize indexes used to map to a tile index in
+    // Board input is a grid of usize indexes used to map to a tile index in
     // the texture atlas
-    // Split file into lines
-    let in_board: Vec<Vec<usize>> = contents
-        .lines()
-        // Split each line by spaces
-        .map(|l| {
-            l.split(' ')
-                // Parse as a usize
-                .map(|s| s.parse::<usize>().unwrap())
-                // Collect the split line into a single vector
-                .collect::<Vec<usize>>()
-        })
-        // Collect all the line vectors into a 2D vector
-        .collect::<Vec<Vec<usize>>>();
+    let in_board = &input.board;
 
     //PASS 1 - GATHER TILE FREQUENCY INFORMATION
+    // Frequencies are kept in percent, so weight overrides can scale them
     for line in in_board.iter() {
         for col in line.iter() {
-            *freqs.entry(*col).or_insert(0) += 1;
+            *freqs.entry(*col).or_insert(0) += input.weight(*col);
         }
     }
 
     //PASS 2 - GATHER LEGAL NEIGHBOR INFORMATION
+    // Learn from the board as written, plus every mirrored or rotated copy
+    // of it that the input's symmetry flags allow
+    for in_board in augmented_boards(input, tiles) {
+        learn_neighbors(&in_board, rules);
+    }
+}
+
+/// Add every pair of tiles seen next to each other on a board to their rules.
+/// `None` cells don't teach anything.
+fn learn_neighbors(in_board: &[Vec<Option<usize>>], rules: &mut HashMap<usize, Rule>) {
     // Iterate over the board in row major order and generate rules
     for (row, line) in in_board.iter().enumerate() {
         for (col, tile_type) in line.iter().enumerate() {
+            // Tiles with no counterpart in this orientation don't teach anything
+            let Some(tile_type) = tile_type else {
+                continue;
+            };
             // Get the adjacency rules for this tile type if they exist,
             // or insert a new adjacency rule map if none exist.
             // The adjacency matrix will be a hashmap mapping from neighbor
             // direction to a vector of allowed tiles for that direction.
-            let cur = rules.entry(*tile_type as usize).or_insert(Rule {
+            let cur = rules.entry(*tile_type).or_insert(Rule {
                 neighbor_rules: HashMap::from([
                     (Dir::WEST, Vec::new()),
                     (Dir::NORTH, Vec::new()),
                     (Dir::EAST, Vec::new()),
                     (Dir::SOUTH, Vec::new()),
                 ]),
-                freq: freqs[tile_type],
+                // Filled in once every input has been read
+                freq: 0,
             });
 
             // Below we actually add the neighbors we see on this iteration to
@@ -112,9 +777,10 @@ pub(crate) fn rulegen(
             row.checked_sub(1)
                 .and_then(|r| in_board.get(r))
                 .and_then(|c| c.get(col))
+                .and_then(|e| *e)
                 .map(|e| {
                     // Get type of northern neighbor
-                    let north_type = *e as usize;
+                    let north_type = e;
                     // Add this type to the allowed types
                     // if it doesn't already exist there.
                     cur.neighbor_rules.entry(Dir::NORTH).and_modify(|allowed| {
@@ -131,8 +797,9 @@ pub(crate) fn rulegen(
             row.checked_add(1)
                 .and_then(|r| in_board.get(r))
                 .and_then(|c| c.get(col))
+                .and_then(|e| *e)
                 .map(|e| {
-                    let north_type = *e as usize;
+                    let north_type = e;
                     cur.neighbor_rules.entry(Dir::SOUTH).and_modify(|allowed| {
                         if !allowed.contains(&north_type) {
                             allowed.push(north_type);
@@ -145,8 +812,9 @@ pub(crate) fn rulegen(
             //WEST
             col.checked_sub(1)
                 .and_then(|col| in_board[row].get(col))
+                .and_then(|char| *char)
                 .map(|char| {
-                    let north_type = *char as usize;
+                    let north_type = char;
                     cur.neighbor_rules.entry(Dir::WEST).and_modify(|allowed| {
                         if !allowed.contains(&north_type) {
                             allowed.push(north_type);
@@ -159,8 +827,9 @@ pub(crate) fn rulegen(
             //EAST
             col.checked_add(1)
                 .and_then(|col| in_board[row].get(col))
+                .and_then(|char| *char)
                 .map(|char| {
-                    let north_type = *char as usize;
+                    let north_type = char;
                     cur.neighbor_rules.entry(Dir::EAST).and_modify(|allowed| {
                         if !allowed.contains(&north_type) {
                             allowed.push(north_type);
@@ -175,431 +844,1090 @@ pub(crate) fn rulegen(
     //println!("rulegen done \n {:?}", rules);
 }
 
-/// Generate a fixed (map) sized screen using wave function collapse
-/// and return a 2D vector of indexes into the texture atlas.
-pub(crate) fn wfc(
-    seeding: Option<Vec<(usize, (usize, usize))>>,
-    rules: HashMap<usize, Rule>,
-) -> Vec<Vec<usize>> {
-    // let rules = rulegen("assets/backgrounds/wfc_inputs/input.txt");
-
-    // Create the board with a specific height and width
-    // (HEIGHT COMES FIRST because ROW MAJOR order)
-    // and the rules and tile types the board will use.
-    let mut board = Board::new(
-        (MAP_HEIGHT, MAP_WIDTH),
-        rules.clone(),
-        rules.keys().copied().collect::<Vec<usize>>(),
-        seeding.clone(),
-    );
-
-    // The result of our WFC operation, what we will want to return.
-    let mut result_map: Vec<Vec<usize>> = Vec::new();
-
-    // Let's spawn a new thread to do this with a large amount of stack memory
-    // because of how many calls this might make.
-    let builder = thread::Builder::new()
-        .stack_size(4194304)
-        .name("generation thread".to_string());
-
-    // Handler just is so we can join the thread back in
-    let handler = builder
-        .spawn(move || {
-            // Collapse gives us a boolean telling us whether the
-            // board is in a collapsable state or if no valid option is available here.
-            // This is the core part of WFC. Collapse will modify board to
-            // be in a collapsed state, if possible.
-            let attempts = 0usize;
-            while attempts < MAX_REPICK_ATTEMPTS {
-                let solvable = board.collapse(
-                    // This choose function picks the tile on the board with the least entropy
-                    // (that means the least number of possible states)
-                    board.choose_tile_to_collapse(),
-                );
-                // println!("solved on attempt {attempts}? {solvable:?}");
-                // println!("\n");
-                if !solvable {
-                    board = Board::new(
-                        (MAP_HEIGHT, MAP_WIDTH),
-                        rules.clone(),
-                        rules.keys().copied().collect::<Vec<usize>>(),
-                        seeding.clone(),
-                    );
-                } else {
-                    break;
-                }
+/// Every NxN window of a biome's sample boards, for the overlapping model.
+///
+/// A cell collapsed to a pattern gets the pattern's top left tile, and neighboring
+/// cells must hold patterns that agree wherever they overlap, so whole windows of
+/// the samples get copied over instead of single tiles.
+#[derive(Debug, Clone)]
+pub struct Patterns {
+    /// Tiles of each pattern in row major order
+    pub patterns: Vec<Vec<Vec<usize>>>,
+    /// How often each pattern shows up in the samples,
+    /// scaled by the spawn weight of its top left tile
+    pub freqs: Vec<usize>,
+    /// For each pattern and direction, the patterns allowed next to it there
+    compatible: Vec<[Vec<usize>; 4]>,
+}
 
-                // attempts += 1;
-                // warn!(
-                //     "WFC could not collapse. Retrying, attempt {}.",
-                //     attempts + 1
-                // );
-            }
-
-            // Put the board contents into the result
-            // so we can return it.
-            for row in &board.map {
-                let mut result_row: Vec<usize> = Vec::new();
-                for c in row {
-                    // print!("{}", char::from_u32(c.t.unwrap() as u32).unwrap());
-                    // print!("{:02X} ", c.t.unwrap());
-                    result_row.push(c.t.unwrap());
+/// Collect every `size`x`size` window of the inputs' boards, and of their mirrored
+/// and rotated copies, along with which windows can overlap each other
+pub fn patterngen(inputs: &[WfcInput], size: usize, tiles: &TileRegistry) -> Patterns {
+    let mut index: HashMap<Vec<Vec<usize>>, usize> = HashMap::new();
+    let mut patterns: Vec<Vec<Vec<usize>>> = Vec::new();
+    let mut seen: Vec<usize> = Vec::new();
+
+    for input in inputs {
+        for board in augmented_boards(input, tiles) {
+            let (height, width) = (board.len(), board[0].len());
+            for row in 0..(height + 1).saturating_sub(size) {
+                for col in 0..(width + 1).saturating_sub(size) {
+                    // Windows with a tile that has no counterpart in this orientation are skipped
+                    let pattern = board[row..row + size]
+                        .iter()
+                        .map(|line| line[col..col + size].iter().copied().collect())
+                        .collect::<Option<Vec<Vec<usize>>>>();
+                    let Some(pattern) = pattern else {
+                        continue;
+                    };
+
+                    let weight = input.weight(pattern[0][0]);
+                    let i = match index.get(&pattern) {
+                        Some(i) => *i,
+                        None => {
+                            index.insert(pattern.clone(), patterns.len());
+                            patterns.push(pattern);
+                            seen.push(0);
+                            patterns.len() - 1
+                        }
+                    };
+                    seen[i] += weight;
                 }
-                result_map.push(result_row);
-                // print!("\n")
             }
+        }
+    }
 
-            // Return the map we created from the thread
-            result_map
+    // Same scaling as tile frequencies in `init_rules`
+    let freqs = patterns
+        .iter()
+        .zip(&seen)
+        .map(|(pattern, seen)| {
+            let spawn_weight = tiles
+                .get(pattern[0][0])
+                .map_or(DEFAULT_SPAWN_WEIGHT, |info| info.spawn_weight);
+            (seen * spawn_weight / DEFAULT_SPAWN_WEIGHT).max(1)
         })
-        .unwrap();
+        .collect();
+
+    // Check whether `below` fits `down` rows and `right` columns from `above`
+    let overlaps = |above: &Vec<Vec<usize>>, below: &Vec<Vec<usize>>, down: usize, right: usize| {
+        (down..size).all(|r| (right..size).all(|c| above[r][c] == below[r - down][c - right]))
+    };
+    let compatible = patterns
+        .iter()
+        .map(|p| {
+            Dir::ALL.map(|dir| {
+                (0..patterns.len())
+                    .filter(|q| {
+                        let q = &patterns[*q];
+                        match dir {
+                            Dir::WEST => overlaps(q, p, 0, 1),
+                            Dir::NORTH => overlaps(q, p, 1, 0),
+                            Dir::EAST => overlaps(p, q, 0, 1),
+                            Dir::SOUTH => overlaps(p, q, 1, 0),
+                        }
+                    })
+                    .collect()
+            })
+        })
+        .collect();
 
-    // Join thread into parent
-    handler.join().unwrap()
+    Patterns {
+        patterns,
+        freqs,
+        compatible,
+    }
+}
 
-    //println!("{:?}", board.map);
+/// Tiles along the facing borders of the chunks next to the one being generated.
+///
+/// Every cell on the new chunk's border is limited to tile types that the rules allow
+/// next to the tile across the border, so the two chunks meet without a seam.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ChunkEdges {
+    /// Bottom row of the chunk above, left to right
+    pub north: Option<Vec<usize>>,
+    /// Top row of the chunk below, left to right
+    pub south: Option<Vec<usize>>,
+    /// Leftmost column of the chunk to the east, top to bottom
+    pub east: Option<Vec<usize>>,
+    /// Rightmost column of the chunk to the west, top to bottom
+    pub west: Option<Vec<usize>>,
+    /// Nearest tile of each diagonal neighbor, in the order
+    /// north-west, north-east, south-west, south-east
+    pub corners: [Option<usize>; 4],
+    /// Tiles the border cells on each side may be, in `Dir::ALL` order
+    pub border_tiles: [Option<Vec<usize>>; 4],
 }
 
-//TODO: reconsider internalizing the map within this struct.
-//because it is a local variable, we cant pass around references to tiles within it without the borrow checker getting mad
-//so we instead have to refer to tiles by coords: (usize,usize)
-#[derive(Debug, Clone)]
-struct Board {
-    map: Vec<Vec<Tile>>,
-    rules: HashMap<usize, Rule>,
+impl ChunkEdges {
+    /// Progressively weaker versions of these edges, starting with the full set.
+    ///
+    /// Corners are dropped first, then the border tile limits, then one edge at a time, ending with no
+    /// constraints at all, so generation always has something it can solve.
+    pub fn fallbacks(&self) -> Vec<ChunkEdges> {
+        let mut fallbacks = vec![self.clone()];
+
+        let mut relaxed = self.clone();
+        if relaxed.corners.iter().any(Option::is_some) {
+            relaxed.corners = [None; 4];
+            fallbacks.push(relaxed.clone());
+        }
+
+        if relaxed.border_tiles.iter().any(Option::is_some) {
+            relaxed.border_tiles = Default::default();
+            fallbacks.push(relaxed.clone());
+        }
+
+        for i in 0..4 {
+            let edge = match i {
+                0 => &mut relaxed.west,
+                1 => &mut relaxed.east,
+                2 => &mut relaxed.south,
+                _ => &mut relaxed.north,
+            };
+            if edge.take().is_some() {
+                fallbacks.push(relaxed.clone());
+            }
+        }
+
+        fallbacks
+    }
+}
+
+/// Limits on how hard `wfc()` works on a board before giving up.
+///
+/// Everything is counted in units of work rather than time,
+/// so the same seed gives the same world no matter how fast the machine is.
+#[derive(Debug, Clone, Copy)]
+pub struct WfcBudget {
+    /// Number of fresh starts allowed
+    pub attempts: usize,
+    /// Number of choices a single attempt may undo before it is restarted
+    pub backtracks: usize,
+    /// Number of cells that may be collapsed across all attempts
+    pub steps: usize,
+    /// Number of cells propagation may visit across all attempts
+    pub propagations: usize,
+}
+
+impl Default for WfcBudget {
+    fn default() -> Self {
+        Self {
+            attempts: MAX_REPICK_ATTEMPTS,
+            backtracks: MAX_BACKTRACKS,
+            steps: MAX_STEPS,
+            propagations: MAX_PROPAGATIONS,
+        }
+    }
 }
 
-impl Index<(usize, usize)> for Board {
-    type Output = Tile;
+/// A cell that propagation left with no possible tile types
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Contradiction {
+    /// Row and column of the emptied cell
+    pub cell: (usize, usize),
+    /// Row and column of the neighbor whose update removed the last option,
+    /// or `None` if the cell was emptied directly by seeding, edges or backtracking
+    pub cause: Option<(usize, usize)>,
+}
 
-    fn index(&self, index: (usize, usize)) -> &Self::Output {
-        &self.map[index.0][index.1]
+/// Diagnostics gathered while running `wfc()`
+#[derive(Debug, Clone, Default)]
+pub struct WfcStats {
+    /// Number of fresh starts used
+    pub attempts: usize,
+    /// Number of times propagation emptied a cell
+    pub contradictions: usize,
+    /// Number of choices undone by backtracking
+    pub backtracks: usize,
+    /// Number of cells collapsed
+    pub steps: usize,
+    /// Number of cells propagation visited
+    pub propagations: usize,
+    /// The most recent contradiction, if there was one
+    pub last_contradiction: Option<Contradiction>,
+    /// Time spent in total. Only for reporting, the budget never looks at it.
+    pub elapsed: Duration,
+}
+
+impl WfcStats {
+    /// Count another run's diagnostics towards these
+    pub fn add(&mut self, other: &WfcStats) {
+        self.attempts += other.attempts;
+        self.contradictions += other.contradictions;
+        self.backtracks += other.backtracks;
+        self.steps += other.steps;
+        self.propagations += other.propagations;
+        self.last_contradiction = other.last_contradiction.or(self.last_contradiction);
+        self.elapsed += other.elapsed;
     }
 }
 
-impl IndexMut<(usize, usize)> for Board {
-    fn index_mut(&mut self, index: (usize, usize)) -> &mut Self::Output {
-        &mut self.map[index.0][index.1]
+/// A board that `wfc()` collapsed successfully
+#[derive(Debug, Clone)]
+pub struct Generated {
+    /// Indexes into the texture atlas, in row major order
+    pub tiles: Vec<Vec<usize>>,
+    pub stats: WfcStats,
+}
+
+/// Tiles of a chunk from `ProcGen::generate()`, and what it took to get them
+#[derive(Debug, Clone)]
+pub struct ChunkLayout {
+    pub biome: Biome,
+    /// Indexes into the texture atlas, in row major order
+    pub tiles: Vec<Vec<usize>>,
+    /// Plain ground of the chunk's biome, what carved paths and removed specials turn into
+    pub floor: usize,
+    /// Name of the prefab stamped into the chunk, if it has one
+    pub landmark: Option<String>,
+    /// Number of times the edges had to be relaxed
+    pub relaxations: usize,
+    /// Whether every attempt failed and the chunk is a single tile type
+    pub filled: bool,
+    /// Number of times the chunk was generated again because parts of it couldn't be reached
+    pub regenerations: usize,
+    /// Number of solid tiles that were turned walkable to connect the chunk
+    pub carved: usize,
+    /// Diagnostics of every `wfc()` run it took, failed ones included
+    pub stats: WfcStats,
+}
+
+/// Reasons `wfc()` can fail to collapse a board
+#[derive(Debug, Clone)]
+pub enum WfcError {
+    /// Seeding and edges contradict each other, no board can satisfy them
+    Unsatisfiable(WfcStats),
+    /// Every attempt ran out of backtracks
+    OutOfAttempts(WfcStats),
+    /// The budget for collapsed cells ran out
+    OutOfSteps(WfcStats),
+    /// The budget for propagation ran out
+    OutOfPropagations(WfcStats),
+}
+
+impl WfcError {
+    /// Get the diagnostics gathered before the failure
+    pub fn stats(&self) -> &WfcStats {
+        match self {
+            WfcError::Unsatisfiable(stats)
+            | WfcError::OutOfAttempts(stats)
+            | WfcError::OutOfSteps(stats)
+            | WfcError::OutOfPropagations(stats) => stats,
+        }
     }
 }
 
-impl Board {
-    /// Initialize a board
-    fn new(
-        size: (usize, usize),
-        rules: HashMap<usize, Rule>,
-        tile_types: Vec<usize>,
-        seeding: Option<Vec<(usize, (usize, usize))>>,
-    ) -> Self {
-        let mut map: Vec<Vec<Tile>> = Vec::new();
-
-        for row in 0..size.0 {
-            map.push(Vec::new());
-            for col in 0..size.1 {
-                map[row].push(Tile::fresh((row, col), tile_types.clone()));
-            }
-        }
-
-        if seeding.is_some() {
-            for seed in seeding.unwrap() {
-                match map.get_mut(seed.1 .0).and_then(|r| r.get_mut(seed.1 .1)) {
-                    Some(v) => {
-                        *v = Tile::set(v.coords, seed.0);
-
-                        // Collect neighbors by hand, cannot call functions because they are only for
-                        // existing boards.
-                        let mut n = Neighbors::new();
-                        let pos = v.coords;
-
-                        // let north_idx = (pos.0 - 1, pos.1);
-                        // let south_idx = (pos.0 + 1, pos.1);
-                        // let west_idx = (pos.0, pos.1 - 1);
-                        // let east_idx = (pos.0, pos.1 + 1);
-
-                        // North
-                        n.north = pos
-                            .0
-                            .checked_sub(1)
-                            .and_then(|e| map.get(e))
-                            .map(|f| f[pos.1].clone());
-
-                        // South
-                        n.south = pos
-                            .0
-                            .checked_add(1)
-                            .and_then(|e| map.get(e))
-                            .map(|f| f[pos.1].clone());
-
-                        // West
-                        n.west = pos
-                            .1
-                            .checked_sub(1)
-                            .and_then(|e| map[pos.0].get(e))
-                            .cloned();
-
-                        // East
-                        n.east = pos
-                            .1
-                            .checked_add(1)
-                            .and_then(|e| map[pos.0].get(e))
-                            .cloned();
-
-                        // Do neighbor update
-                        for neighbor in n {
-                            map[neighbor.tile.coords.0][neighbor.tile.coords.1]
-                                .position
-                                .retain(|t| {
-                                    rules[&seed.0].neighbor_rules[&neighbor.direction].contains(t)
-                                });
-                        }
-                    }
-                    None => {}
-                }
+impl fmt::Display for WfcError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let reason = match self {
+            WfcError::Unsatisfiable(_) => "constraints cannot be satisfied",
+            WfcError::OutOfAttempts(_) => "ran out of attempts",
+            WfcError::OutOfSteps(_) => "ran out of steps",
+            WfcError::OutOfPropagations(_) => "ran out of propagations",
+        };
+        let stats = self.stats();
+        write!(
+            f,
+            "{} after {} attempt(s), {} step(s), {} contradiction(s) and {} backtrack(s) in {:?}",
+            reason,
+            stats.attempts,
+            stats.steps,
+            stats.contradictions,
+            stats.backtracks,
+            stats.elapsed
+        )?;
+        if let Some(contradiction) = stats.last_contradiction {
+            write!(f, ", last emptied cell {:?}", contradiction.cell)?;
+            if let Some(cause) = contradiction.cause {
+                write!(f, " next to {:?}", cause)?;
             }
         }
+        Ok(())
+    }
+}
+
+/// Generate a fixed (map) sized screen using wave function collapse
+/// and return a 2D vector of indexes into the texture atlas.
+///
+/// All randomness comes from `rng`, so the same generator, seeding
+/// and edges always give the same screen.
+///
+/// Fails if the constraints cannot be met, or if the board could not be
+/// collapsed within `budget`, with diagnostics about what went wrong.
+pub fn wfc(
+    seeding: Option<Vec<(usize, (usize, usize))>>,
+    edges: &ChunkEdges,
+    rules: &HashMap<usize, Rule>,
+    rng: &mut StdRng,
+    budget: WfcBudget,
+) -> Result<Generated, WfcError> {
+    collapse(Board::tiled(rules), seeding, edges, rules, rng, budget)
+}
 
-        Self { map, rules }
+/// Generate a fixed (map) sized screen like `wfc()`, but with the overlapping model.
+///
+/// Edges and seeding still work on single tiles using `rules`,
+/// so chunks from either model fit next to each other.
+pub fn wfc_overlapping(
+    seeding: Option<Vec<(usize, (usize, usize))>>,
+    edges: &ChunkEdges,
+    patterns: &Patterns,
+    rules: &HashMap<usize, Rule>,
+    rng: &mut StdRng,
+    budget: WfcBudget,
+) -> Result<Generated, WfcError> {
+    collapse(
+        Board::overlapping(patterns),
+        seeding,
+        edges,
+        rules,
+        rng,
+        budget,
+    )
+}
+
+/// Apply the edges and seeding to a fresh board, then collapse it within `budget`
+fn collapse(
+    mut initial: Board,
+    seeding: Option<Vec<(usize, (usize, usize))>>,
+    edges: &ChunkEdges,
+    rules: &HashMap<usize, Rule>,
+    rng: &mut StdRng,
+    budget: WfcBudget,
+) -> Result<Generated, WfcError> {
+    let start = Instant::now();
+    let mut stats = WfcStats::default();
+
+    let constrained = initial.constrain(rules, seeding, edges);
+    stats.propagations = initial.propagations;
+    if let Err(contradiction) = constrained {
+        stats.contradictions += 1;
+        stats.last_contradiction = Some(contradiction);
+        stats.elapsed = start.elapsed();
+        return Err(WfcError::Unsatisfiable(stats));
     }
 
-    // TODO: Very poor runtime, needs optimization.
-    /// This will make sure no tiles on the board are breaking adjacency rules.
-    /// It will NOT check if we have a completed board.
-    fn valid_position(&self) -> bool {
-        for row in &self.map {
-            for col in row {
-                // Empty superpositions are invalid unless the tile has a concrete type
-                if col.position.is_empty() && col.t.is_none() {
-                    return false;
+    while stats.attempts < budget.attempts {
+        stats.attempts += 1;
+        let mut board = initial.clone();
+        let mut backtracks = 0usize;
+
+        while backtracks <= budget.backtracks {
+            if stats.steps >= budget.steps {
+                stats.elapsed = start.elapsed();
+                return Err(WfcError::OutOfSteps(stats));
+            }
+            if stats.propagations >= budget.propagations {
+                stats.elapsed = start.elapsed();
+                return Err(WfcError::OutOfPropagations(stats));
+            }
+
+            let propagations = board.propagations;
+            let step = board.step(rng);
+            stats.propagations += board.propagations - propagations;
+            match step {
+                Step::Collapsed => stats.steps += 1,
+                Step::Backtracked(contradiction) => {
+                    stats.steps += 1;
+                    backtracks += 1;
+                    stats.backtracks += 1;
+                    stats.contradictions += 1;
+                    stats.last_contradiction = Some(contradiction);
+                }
+                Step::Solved => {
+                    stats.elapsed = start.elapsed();
+                    return Ok(Generated {
+                        tiles: board.tiles(),
+                        stats,
+                    });
+                }
+                // Every choice was undone, so there is no way to solve this board
+                Step::Failed(contradiction) => {
+                    stats.contradictions += 1;
+                    stats.last_contradiction = Some(contradiction);
+                    stats.elapsed = start.elapsed();
+                    return Err(WfcError::Unsatisfiable(stats));
                 }
             }
         }
+    }
+
+    stats.elapsed = start.elapsed();
+    Err(WfcError::OutOfAttempts(stats))
+}
+
+/// Generate a small board whose cells belong to different chunks, like the cells on both
+/// sides of a seam, and return its tiles like `wfc()` would.
+///
+/// `owners` has the index into `rule_sets` of every cell's chunk. Cells only get tile types
+/// from their own chunk's rules, and two tiles only end up next to each other if the rules
+/// of both of their chunks allow it, so either chunk can be generated from its side later.
+pub fn wfc_patch(
+    owners: &[Vec<usize>],
+    rule_sets: &[&HashMap<usize, Rule>],
+    seeding: Vec<(usize, (usize, usize))>,
+    rng: &mut StdRng,
+    budget: WfcBudget,
+) -> Result<Generated, WfcError> {
+    // No edges, so the rules passed along only have to be there
+    collapse(
+        Board::patch(owners, rule_sets),
+        Some(seeding),
+        &ChunkEdges::default(),
+        rule_sets[0],
+        rng,
+        budget,
+    )
+}
+
+/// Last resort chunk layout made entirely out of the most common tile type
+pub fn filled_chunk(rules: &HashMap<usize, Rule>) -> Vec<Vec<usize>> {
+    let tile = rules
+        .iter()
+        .max_by_key(|(t, rule)| (rule.freq, **t))
+        .map_or(0, |(t, _)| *t);
+
+    vec![vec![tile; MAP_WIDTH]; MAP_HEIGHT]
+}
+
+/// Most common tile in `rules` that can be walked on and does nothing,
+/// to put wherever a chunk needs plain ground
+fn floor_tile(rules: &HashMap<usize, Rule>, tiles: &TileRegistry) -> usize {
+    rules
+        .iter()
+        .filter(|(t, _)| tiles.walkable(**t) && tiles.interaction(**t) == TileInteraction::None)
+        .max_by_key(|(t, rule)| (rule.freq, **t))
+        .map_or(0, |(t, _)| *t)
+}
+
+/// The most common tile type in `rules` and every tile type that can sit in the middle of it.
+///
+/// A border made of these can always be filled in with the most common tile, so no chunk
+/// gets a border it can't be finished from. Tiles that have to go on in a line,
+/// like roads, can't sit in the middle of anything and are left out.
+fn ground_tiles(rules: &HashMap<usize, Rule>) -> HashSet<usize> {
+    let Some(ground) = rules
+        .iter()
+        .max_by_key(|(t, rule)| (rule.freq, **t))
+        .map(|(t, _)| *t)
+    else {
+        return HashSet::new();
+    };
+    let mut tiles = rules
+        .keys()
+        .copied()
+        .filter(|t| {
+            Dir::ALL.iter().all(|dir| {
+                rules[t].neighbor_rules[dir].contains(&ground)
+                    && rules[&ground].neighbor_rules[&dir.opposite()].contains(t)
+            })
+        })
+        .collect::<HashSet<usize>>();
+    tiles.insert(ground);
+    tiles
+}
 
-        true
+/// Bitset of `words` u64s with the given numbers set
+fn bitset(numbers: impl Iterator<Item = usize>, words: usize) -> Vec<u64> {
+    let mut set = vec![0u64; words];
+    for i in numbers {
+        set[i / 64] |= 1 << (i % 64);
     }
+    set
+}
+
+/// Collapses a board one step at a time, to watch what `wfc()` does with a set of rules
+pub struct WfcStepper {
+    board: Board,
+    rng: StdRng,
+    /// Step to report before collapsing anything, if the constraints already failed
+    first: Option<Step>,
+    finished: bool,
+}
+
+/// What a board looks like after one step of a `WfcStepper`
+#[derive(Debug, Clone)]
+pub struct WfcFrame {
+    pub step: Step,
+    /// Number of options each cell has left
+    pub entropy: Vec<Vec<usize>>,
+    /// Tile of each cell that is down to one option
+    pub tiles: Vec<Vec<Option<usize>>>,
+    /// Number of options every cell started with
+    pub options: usize,
+}
 
-    /// Check that the board is in a solved state to stop collapsing early
-    fn is_solved(&self) -> bool {
-        if !self.valid_position() {
-            return false;
+impl WfcStepper {
+    /// Set up a board like `wfc()` or `wfc_overlapping()` would, without collapsing anything yet
+    pub fn new(
+        seeding: Option<Vec<(usize, (usize, usize))>>,
+        edges: &ChunkEdges,
+        rules: &HashMap<usize, Rule>,
+        patterns: Option<&Patterns>,
+        rng: StdRng,
+    ) -> Self {
+        let mut board = match patterns {
+            Some(patterns) => Board::overlapping(patterns),
+            None => Board::tiled(rules),
+        };
+        let first = board
+            .constrain(rules, seeding, edges)
+            .err()
+            .map(Step::Failed);
+        Self {
+            board,
+            rng,
+            first,
+            finished: false,
         }
+    }
 
-        return !self.map.iter().flatten().any(|t| t.t.is_none());
+    /// Collapse one more cell, backtracking if needed.
+    ///
+    /// Returns `None` once the board is solved or has failed. There is no budget,
+    /// so a board that keeps backtracking can take a very long time to get there.
+    pub fn step(&mut self) -> Option<WfcFrame> {
+        if self.finished {
+            return None;
+        }
+        let step = match self.first.take() {
+            Some(step) => step,
+            None => self.board.step(&mut self.rng),
+        };
+        self.finished = matches!(step, Step::Solved | Step::Failed(_));
+
+        let board = &self.board;
+        Some(WfcFrame {
+            step,
+            entropy: board.grid(|cell| board.entropy(cell)),
+            tiles: board.grid(|cell| match board.entropy(cell) {
+                1 => board.options(cell).next().map(|i| board.tile_types[i]),
+                _ => None,
+            }),
+            options: board.tile_types.len(),
+        })
     }
+}
 
-    /// Choose the tile on the board with the lowest entropy and return its coords within the map
-    fn choose_tile_to_collapse(&self) -> (usize, usize) {
-        // Find the minimum entropy of the board
-        let minimum = self
-            .map
-            .iter()
-            .flatten()
-            .min_by(|x, y| x.entropy().cmp(&y.entropy()))
-            .unwrap()
-            .entropy();
+/// Outcome of a single step of collapsing a board
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Step {
+    /// A cell was collapsed and propagation succeeded
+    Collapsed,
+    /// A collapse led to a contradiction and a choice was undone
+    Backtracked(Contradiction),
+    /// Every cell has exactly one tile type left
+    Solved,
+    /// The contradiction could not be undone, the board has no solution
+    Failed(Contradiction),
+}
 
-        // Collect all tiles into a vector first, we'll operate on this because it's easier
-        let mut random_tiles: Vec<&Tile> = self.map.iter().flatten().collect::<Vec<&Tile>>();
+/// A choice made while collapsing, kept so it can be undone
+#[derive(Debug, Clone)]
+struct Frame {
+    /// Superpositions of every cell before the choice was made
+    snapshot: Vec<u64>,
+    /// Index of the cell that was collapsed
+    cell: usize,
+    /// Tile type index the cell was collapsed to
+    choice: usize,
+}
 
-        // Retain only tiles with the same entropy as the minimum
-        random_tiles.retain(|tile| tile.entropy() == minimum);
+/// Wave function collapse board.
+///
+/// Options (tile types, or patterns for the overlapping model) are numbered `0..tile_types.len()`,
+/// and each cell's superposition is a bitset over those numbers stored flat in `positions`,
+/// `words` u64s per cell.
+#[derive(Debug, Clone)]
+struct Board {
+    /// Height and width in cells
+    size: (usize, usize),
+    /// Number of u64s used for one cell's superposition
+    words: usize,
+    /// Superposition of every cell in row major order
+    positions: Vec<u64>,
+    /// Texture atlas index of each option
+    tile_types: Vec<usize>,
+    /// Sampling weight of each option
+    weights: Vec<usize>,
+    /// For each option and direction, the set of options allowed there
+    propagator: Vec<[Vec<u64>; 4]>,
+    /// For each option and direction, the set of options that allow it in that direction,
+    /// the propagator turned around
+    supporters: Vec<[Vec<u64>; 4]>,
+    /// Choices that can still be backtracked
+    stack: Vec<Frame>,
+    /// Number of cells `propagate()` has visited, which is what most of the work goes into
+    propagations: usize,
+}
 
-        // Shuffle these tiles up in case more than one tile matches
-        random_tiles.shuffle(&mut thread_rng());
+impl Board {
+    /// Initialize a board where every cell can still be any option
+    fn new(
+        size: (usize, usize),
+        tile_types: Vec<usize>,
+        weights: Vec<usize>,
+        propagator: Vec<[Vec<u64>; 4]>,
+    ) -> Self {
+        let words = tile_types.len().div_ceil(64);
+        let full = bitset(0..tile_types.len(), words);
+
+        let mut supporters = vec![[(); 4].map(|_| vec![0u64; words]); tile_types.len()];
+        for (i, dirs) in propagator.iter().enumerate() {
+            for (dir, allowed) in dirs.iter().enumerate() {
+                for j in 0..tile_types.len() {
+                    if allowed[j / 64] & (1 << (j % 64)) != 0 {
+                        supporters[j][dir][i / 64] |= 1 << (i % 64);
+                    }
+                }
+            }
+        }
 
-        // Pick the first of this random ordering
-        random_tiles[0].coords
+        Self {
+            size,
+            words,
+            positions: full.repeat(size.0 * size.1),
+            weights,
+            tile_types,
+            propagator,
+            supporters,
+            stack: Vec::new(),
+            propagations: 0,
+        }
     }
 
-    /// Returns a NEW neighbors struct, with indices into the map of the neighboring tiles
-    /// Note that this DOES NOT return references to the ACTUAL neighbors on the maps
-    fn get_neighbors(&self, pos: (usize, usize)) -> Neighbors {
-        let mut n = Neighbors::new();
-
-        //north
-        n.north = pos
-            .0
-            .checked_sub(1)
-            .and_then(|e| self.map.get(e))
-            .map(|f| f[pos.1].clone());
-
-        //south
-        n.south = pos
-            .0
-            .checked_add(1)
-            .and_then(|e| self.map.get(e))
-            .map(|f| f[pos.1].clone());
+    /// Initialize a map sized board for the simple tiled model
+    fn tiled(rules: &HashMap<usize, Rule>) -> Self {
+        // HashMap key order changes from run to run, so sort the tile types
+        // to keep weighted sampling reproducible.
+        let mut tile_types = rules.keys().copied().collect::<Vec<usize>>();
+        tile_types.sort_unstable();
+        let number_of: HashMap<usize, usize> = tile_types
+            .iter()
+            .enumerate()
+            .map(|(i, t)| (*t, i))
+            .collect();
 
-        //west
-        n.west = pos
-            .1
-            .checked_sub(1)
-            .and_then(|e| self.map[pos.0].get(e))
-            .cloned();
+        // Turn each rule's neighbor lists into bitsets over tile type numbers
+        let words = tile_types.len().div_ceil(64);
+        let propagator = tile_types
+            .iter()
+            .map(|t| {
+                Dir::ALL.map(|dir| {
+                    let allowed = rules[t].neighbor_rules[&dir].iter();
+                    bitset(allowed.filter_map(|n| number_of.get(n).copied()), words)
+                })
+            })
+            .collect();
+        let weights = tile_types.iter().map(|t| rules[t].freq).collect();
+
+        // Create the board with a specific height and width
+        // (HEIGHT COMES FIRST because ROW MAJOR order)
+        // and the rules and tile types the board will use.
+        Board::new((MAP_HEIGHT, MAP_WIDTH), tile_types, weights, propagator)
+    }
 
-        //east
-        n.east = pos
-            .1
-            .checked_add(1)
-            .and_then(|e| self.map[pos.0].get(e))
-            .cloned();
+    /// Initialize a board for `wfc_patch()`, with every cell limited to the `ground_tiles()` of its owner.
+    ///
+    /// Each rule set gets options of its own, even for tile types another one has too.
+    fn patch(owners: &[Vec<usize>], rule_sets: &[&HashMap<usize, Rule>]) -> Self {
+        let mut tile_types: Vec<usize> = Vec::new();
+        // Rule set of each option, and the first option of each rule set
+        let mut owner_of: Vec<usize> = Vec::new();
+        let mut first: Vec<usize> = Vec::new();
+        for (owner, rules) in rule_sets.iter().enumerate() {
+            let mut types = ground_tiles(rules).into_iter().collect::<Vec<usize>>();
+            types.sort_unstable();
+            first.push(tile_types.len());
+            owner_of.extend(types.iter().map(|_| owner));
+            tile_types.extend(types);
+        }
+        first.push(tile_types.len());
+
+        let words = tile_types.len().div_ceil(64);
+        let allows = |i: usize, dir: Dir, j: usize| {
+            rule_sets[owner_of[i]][&tile_types[i]].neighbor_rules[&dir].contains(&tile_types[j])
+        };
+        let propagator = (0..tile_types.len())
+            .map(|i| {
+                Dir::ALL.map(|dir| {
+                    let allowed = (0..tile_types.len())
+                        .filter(|&j| allows(i, dir, j) && allows(j, dir.opposite(), i));
+                    bitset(allowed, words)
+                })
+            })
+            .collect();
+        let weights = (0..tile_types.len())
+            .map(|i| rule_sets[owner_of[i]][&tile_types[i]].freq)
+            .collect();
+
+        let size = (owners.len(), owners.first().map_or(0, Vec::len));
+        let mut board = Board::new(size, tile_types, weights, propagator);
+        for (cell, owner) in owners.iter().flatten().enumerate() {
+            let own = bitset(first[*owner]..first[*owner + 1], words);
+            board.positions[cell * words..(cell + 1) * words].copy_from_slice(&own);
+        }
+        board
+    }
 
-        n
+    /// Initialize a map sized board for the overlapping model
+    fn overlapping(patterns: &Patterns) -> Self {
+        let words = patterns.patterns.len().div_ceil(64);
+        let propagator = patterns
+            .compatible
+            .iter()
+            .map(|dirs| {
+                dirs.each_ref()
+                    .map(|allowed| bitset(allowed.iter().copied(), words))
+            })
+            .collect();
+        let tile_types = patterns.patterns.iter().map(|p| p[0][0]).collect();
+
+        Board::new(
+            (MAP_HEIGHT, MAP_WIDTH),
+            tile_types,
+            patterns.freqs.clone(),
+            propagator,
+        )
     }
 
-    /// Update the neighbors of this tile to reflect a change in our state.
+    /// Apply the edges and seeding and propagate their effects.
     ///
-    /// Calls a `.retain()` on each neighbor's position to only keep subpositions
-    /// that are members of our neighbor-ruleset.
-    fn update_neighbors(&mut self, center_tile: (usize, usize), selected_pos: usize) {
-        for neighbor in self.get_neighbors(center_tile) {
-            // Major issue, this looks like the code to ACTUALLY update the tile's positions in our map,
-            // the above only affects a stack allocated tile that is destroyed when the function ends.
-            // Yet, when we let the bottom code run, the game crashes almost 50% of the time because it cannot
-            // find a valid state. This means that the backtracking algorithm straight up *doesn't work at all.*
-            self.map[neighbor.tile.coords.0][neighbor.tile.coords.1]
-                .position
-                .retain(|t| {
-                    self.rules[&selected_pos].neighbor_rules[&neighbor.direction].contains(t)
+    /// Fails with the first contradiction if the constraints cannot all be met.
+    fn constrain(
+        &mut self,
+        rules: &HashMap<usize, Rule>,
+        seeding: Option<Vec<(usize, (usize, usize))>>,
+        edges: &ChunkEdges,
+    ) -> Result<(), Contradiction> {
+        let size = self.size;
+
+        // Limit border tiles to what may sit next to the neighboring chunks
+        let (last_row, last_col) = (size.0 - 1, size.1 - 1);
+        let allowed_next_to = |t: usize, dir: Dir, other: usize| -> bool {
+            rules[&t].neighbor_rules[&dir].contains(&other)
+        };
+        if let Some(north) = &edges.north {
+            for (col, other) in north.iter().enumerate().take(size.1) {
+                self.restrict((0, col), |t| allowed_next_to(t, Dir::NORTH, *other));
+            }
+        }
+        if let Some(south) = &edges.south {
+            for (col, other) in south.iter().enumerate().take(size.1) {
+                self.restrict((last_row, col), |t| allowed_next_to(t, Dir::SOUTH, *other));
+            }
+        }
+        if let Some(east) = &edges.east {
+            for (row, other) in east.iter().enumerate().take(size.0) {
+                self.restrict((row, last_col), |t| allowed_next_to(t, Dir::EAST, *other));
+            }
+        }
+        if let Some(west) = &edges.west {
+            for (row, other) in west.iter().enumerate().take(size.0) {
+                self.restrict((row, 0), |t| allowed_next_to(t, Dir::WEST, *other));
+            }
+        }
+
+        // Keep each border to tiles the biomes on the other side know about
+        for (dir, allowed) in Dir::ALL.iter().zip(&edges.border_tiles) {
+            if let Some(allowed) = allowed {
+                let cells: Vec<(usize, usize)> = match dir {
+                    Dir::WEST => (0..size.0).map(|row| (row, 0)).collect(),
+                    Dir::NORTH => (0..size.1).map(|col| (0, col)).collect(),
+                    Dir::EAST => (0..size.0).map(|row| (row, last_col)).collect(),
+                    Dir::SOUTH => (0..size.1).map(|col| (last_row, col)).collect(),
+                };
+                for cell in cells {
+                    self.restrict(cell, |t| allowed.contains(&t));
+                }
+            }
+        }
+
+        // Diagonal neighbors only touch a corner, so a corner tile just needs
+        // some tile between it and the diagonal that both of them allow.
+        let corner_cells = [
+            ((0, 0), Dir::NORTH, Dir::WEST),
+            ((0, last_col), Dir::NORTH, Dir::EAST),
+            ((last_row, 0), Dir::SOUTH, Dir::WEST),
+            ((last_row, last_col), Dir::SOUTH, Dir::EAST),
+        ];
+        for (corner, (cell, vertical, horizontal)) in edges.corners.iter().zip(corner_cells) {
+            if let Some(other) = corner {
+                let reaches = |t: usize, first: Dir, second: Dir| -> bool {
+                    rules[&t].neighbor_rules[&first].iter().any(|between| {
+                        rules
+                            .get(between)
+                            .is_some_and(|r| r.neighbor_rules[&second].contains(other))
+                    })
+                };
+                self.restrict(cell, |t| {
+                    reaches(t, vertical, horizontal) || reaches(t, horizontal, vertical)
                 });
+            }
         }
-    }
 
-    /// Takes 1 tile, collapses its state down to a concrete type, and udpates its neighbors' superpositions.
-    /// Returns true on success or false on failure.
-    fn collapse(&mut self, center_tile: (usize, usize)) -> bool {
-        // If our board is already marked as solved, we're done here,
-        // jump back up the stack.
-        if self.is_solved() {
-            return true;
+        // Seeded tiles are fixed to exactly one type
+        for (kind, cell) in seeding.unwrap_or_default() {
+            if cell.0 < size.0 && cell.1 < size.1 {
+                self.restrict(cell, |t| t == kind);
+            }
         }
 
-        // info!("collapsing {:?}", center_tile);
+        // Make sure nothing was emptied above, then spread every restriction
+        // across the whole board before any collapsing happens.
+        let cells = size.0 * size.1;
+        if let Some(empty) = (0..cells).find(|c| self.entropy(*c) == 0) {
+            return Err(Contradiction {
+                cell: self.coords(empty),
+                cause: None,
+            });
+        }
+        self.propagate((0..cells).collect())
+    }
 
-        // center_tile is the tile we are pivoting collapse on right now.
-        // Get the super position of the tile, back it up,
-        // and empty out the position of this tile.
+    /// Row and column of a cell index
+    fn coords(&self, cell: usize) -> (usize, usize) {
+        (cell / self.size.1, cell % self.size.1)
+    }
 
-        let mut random_pos = self[center_tile].position.clone();
-        let weight_dist =
-            WeightedIndex::new(random_pos.iter().map(|pos| self.rules[pos].freq)).unwrap();
+    /// Superposition of a cell
+    fn position(&self, cell: usize) -> &[u64] {
+        &self.positions[cell * self.words..(cell + 1) * self.words]
+    }
 
-        let backup_pos = self[center_tile].position.clone();
-        self[center_tile].position = Vec::new();
+    /// Options a cell could still be
+    fn options(&self, cell: usize) -> impl Iterator<Item = usize> + '_ {
+        // Walk the set bits directly, pattern sets are big and mostly empty once collapsing starts
+        self.position(cell)
+            .iter()
+            .enumerate()
+            .flat_map(|(w, bits)| {
+                let mut bits = *bits;
+                std::iter::from_fn(move || {
+                    if bits == 0 {
+                        return None;
+                    }
+                    let i = bits.trailing_zeros() as usize;
+                    bits &= bits - 1;
+                    Some(w * 64 + i)
+                })
+            })
+    }
 
-        // Shuffle up the position and check all of them to find one that is valid.
-        // This shuffle gives us the randomality of WFC
-        //random_pos.shuffle(&mut thread_rng());
+    /// Determine what the entropy of a cell is
+    ///
+    /// Entropy is defined as the number of possible subpositions
+    /// in this cell's superposition.
+    fn entropy(&self, cell: usize) -> usize {
+        self.position(cell)
+            .iter()
+            .map(|w| w.count_ones() as usize)
+            .sum()
+    }
 
-        //for pos in random_pos {
-        for _i in 0..random_pos.len() {
-            // weight_dist = WeightedIndex::new(random_pos.iter().map(|pos| self.rules[pos].freq)).unwrap();
-            let mut pos = backup_pos[weight_dist.sample(&mut thread_rng())];
-            while !random_pos.contains(&pos) {
-                pos = backup_pos[weight_dist.sample(&mut thread_rng())];
+    /// Remove every tile type from a cell's superposition that `keep` rejects
+    fn restrict(&mut self, cell: (usize, usize), keep: impl Fn(usize) -> bool) {
+        let index = cell.0 * self.size.1 + cell.1;
+        for i in self.options(index).collect::<Vec<_>>() {
+            if !keep(self.tile_types[i]) {
+                self.positions[index * self.words + i / 64] &= !(1 << (i % 64));
             }
-            random_pos.retain(|e| *e != pos);
-            // Make sure even our backup doesn't try this again
-            // backup_pos.retain(|e| *e != pos);
+        }
+    }
 
-            // Tentatively give our tile this concrete position
-            self[center_tile].t = Some(pos);
+    /// Index of the neighboring cell in a direction, if it is on the board
+    fn neighbor(&self, cell: usize, dir: Dir) -> Option<usize> {
+        let (row, col) = self.coords(cell);
+        let (row, col) = match dir {
+            Dir::NORTH => (row.checked_sub(1)?, col),
+            Dir::SOUTH => (row + 1, col),
+            Dir::WEST => (row, col.checked_sub(1)?),
+            Dir::EAST => (row, col + 1),
+        };
+        if row < self.size.0 && col < self.size.1 {
+            Some(row * self.size.1 + col)
+        } else {
+            None
+        }
+    }
 
-            // Backup our neighbors and update neighbors' superpositions
-            // according to the current subposition we are trying to collapse
-            let old_neighbors = self.get_neighbors(center_tile);
-            // Update neighbors' positions
-            self.update_neighbors(center_tile, pos);
+    /// Spread the effects of changed cells across the board until nothing else changes.
+    ///
+    /// Every neighbor of a changed cell keeps only the tile types that at least one
+    /// remaining option of the changed cell allows, which is full arc consistency
+    /// rather than just updating the direct neighbors of a collapse.
+    fn propagate(&mut self, pending: Vec<usize>) -> Result<(), Contradiction> {
+        let mut queued = vec![false; self.size.0 * self.size.1];
+        for cell in &pending {
+            queued[*cell] = true;
+        }
 
-            // If this subposition is a valid position,
-            // call solve on the next tile to be collapse
-            if self.valid_position() {
-                // if we are not in a solved board, continue recursing, otherwise, return our way up the call stack
-                if self.collapse(self.choose_tile_to_collapse()) {
-                    return true;
+        // First in first out, so changes spread in waves instead of
+        // revisiting the same cells over and over
+        let mut pending = VecDeque::from(pending);
+        while let Some(cell) = pending.pop_front() {
+            queued[cell] = false;
+            self.propagations += 1;
+
+            for dir in Dir::ALL {
+                let neighbor = match self.neighbor(cell, dir) {
+                    Some(neighbor) => neighbor,
+                    None => continue,
+                };
+
+                // Everything the neighbor may be given what this cell may be,
+                // worked out from whichever side has fewer options left
+                let mut support = vec![0u64; self.words];
+                if self.entropy(cell) <= self.entropy(neighbor) {
+                    for i in self.options(cell) {
+                        for (s, allowed) in
+                            support.iter_mut().zip(&self.propagator[i][dir as usize])
+                        {
+                            *s |= allowed;
+                        }
+                    }
                 } else {
-                    // Do not return a success if we could not collapse any neighbor
+                    let position = self.position(cell);
+                    for j in self.options(neighbor) {
+                        let supporters = &self.supporters[j][dir as usize];
+                        if supporters.iter().zip(position).any(|(a, b)| a & b != 0) {
+                            support[j / 64] |= 1 << (j % 64);
+                        }
+                    }
+                }
+
+                let mut changed = false;
+                let mut empty = true;
+                let start = neighbor * self.words;
+                for (w, s) in self.positions[start..start + self.words]
+                    .iter_mut()
+                    .zip(&support)
+                {
+                    let narrowed = *w & s;
+                    changed |= narrowed != *w;
+                    empty &= narrowed == 0;
+                    *w = narrowed;
                 }
-            } else {
-                // If we're not in a valid position,
-                // set ourselves back to what we were before
-                // trying and then we will try the next subpos.
-                for n in old_neighbors {
-                    self[n.tile.coords] = n.tile.clone();
+
+                if changed {
+                    if empty {
+                        return Err(Contradiction {
+                            cell: self.coords(neighbor),
+                            cause: Some(self.coords(cell)),
+                        });
+                    }
+                    if !queued[neighbor] {
+                        queued[neighbor] = true;
+                        pending.push_back(neighbor);
+                    }
                 }
             }
         }
-        // Reset ourselves and fail if no position
-        // ever succeeded.
-        self[center_tile].t = None;
-        self[center_tile].position = backup_pos;
-        false
-    }
-}
-
-#[derive(Debug)]
-struct Neighbors {
-    north: Option<Tile>,
-    south: Option<Tile>,
-    east: Option<Tile>,
-    west: Option<Tile>,
-}
-
-struct NeighborIterElement {
-    direction: Dir,
-    tile: Tile,
-}
 
-impl IntoIterator for Neighbors {
-    type Item = NeighborIterElement;
-    type IntoIter = std::vec::IntoIter<NeighborIterElement>;
+        Ok(())
+    }
 
-    fn into_iter(self) -> Self::IntoIter {
-        let mut neighbors: Vec<NeighborIterElement> = Vec::new();
+    /// Choose an uncollapsed cell with the lowest entropy, breaking ties randomly.
+    ///
+    /// Returns `None` once every cell is collapsed.
+    fn choose_tile_to_collapse(&self, rng: &mut StdRng) -> Option<usize> {
+        let cells = self.size.0 * self.size.1;
+        let minimum = (0..cells)
+            .map(|c| self.entropy(c))
+            .filter(|e| *e > 1)
+            .min()?;
+
+        let candidates = (0..cells)
+            .filter(|c| self.entropy(*c) == minimum)
+            .collect::<Vec<usize>>();
+        candidates.choose(rng).copied()
+    }
 
-        if let Some(f) = self.north {
-            neighbors.push(NeighborIterElement {
-                direction: Dir::NORTH,
-                tile: f,
-            });
+    /// Pick one of a cell's remaining options, weighted by tile frequency
+    fn pick_weighted(&self, cell: usize, rng: &mut StdRng) -> usize {
+        let options = self.options(cell).collect::<Vec<usize>>();
+        let total: usize = options.iter().map(|i| self.weights[*i]).sum();
+        if total == 0 {
+            return options[rng.gen_range(0..options.len())];
         }
 
-        if let Some(f) = self.south {
-            neighbors.push(NeighborIterElement {
-                direction: Dir::SOUTH,
-                tile: f,
-            });
+        let mut roll = rng.gen_range(0..total);
+        for i in &options {
+            if roll < self.weights[*i] {
+                return *i;
+            }
+            roll -= self.weights[*i];
         }
+        options[options.len() - 1]
+    }
 
-        if let Some(f) = self.east {
-            neighbors.push(NeighborIterElement {
-                direction: Dir::EAST,
-                tile: f,
-            });
+    /// Collapse the lowest entropy cell and propagate, backtracking if that fails.
+    fn step(&mut self, rng: &mut StdRng) -> Step {
+        let cell = match self.choose_tile_to_collapse(rng) {
+            Some(cell) => cell,
+            None => return Step::Solved,
+        };
+        let choice = self.pick_weighted(cell, rng);
+
+        self.stack.push(Frame {
+            snapshot: self.positions.clone(),
+            cell,
+            choice,
+        });
+        let start = cell * self.words;
+        self.positions[start..start + self.words].fill(0);
+        self.positions[start + choice / 64] = 1 << (choice % 64);
+
+        match self.propagate(vec![cell]) {
+            Ok(()) => Step::Collapsed,
+            Err(contradiction) => self.backtrack(contradiction),
         }
+    }
 
-        if let Some(f) = self.west {
-            neighbors.push(NeighborIterElement {
-                direction: Dir::WEST,
-                tile: f,
-            });
+    /// Undo choices until one can be ruled out without another contradiction.
+    fn backtrack(&mut self, mut contradiction: Contradiction) -> Step {
+        while let Some(frame) = self.stack.pop() {
+            self.positions = frame.snapshot;
+            let start = frame.cell * self.words;
+            self.positions[start + frame.choice / 64] &= !(1 << (frame.choice % 64));
+
+            if self.entropy(frame.cell) == 0 {
+                contradiction = Contradiction {
+                    cell: self.coords(frame.cell),
+                    cause: None,
+                };
+                continue;
+            }
+            match self.propagate(vec![frame.cell]) {
+                Ok(()) => return Step::Backtracked(contradiction),
+                Err(next) => contradiction = next,
+            }
         }
 
-        neighbors.into_iter()
+        Step::Failed(contradiction)
     }
-}
 
-impl Neighbors {
-    fn new() -> Self {
-        Self {
-            north: None,
-            south: None,
-            east: None,
-            west: None,
-        }
+    /// Something about every cell, laid out in rows
+    fn grid<T>(&self, f: impl Fn(usize) -> T) -> Vec<Vec<T>> {
+        (0..self.size.0)
+            .map(|row| {
+                (0..self.size.1)
+                    .map(|col| f(row * self.size.1 + col))
+                    .collect()
+            })
+            .collect()
+    }
+
+    /// Texture atlas indexes of a fully collapsed board
+    fn tiles(&self) -> Vec<Vec<usize>> {
+        self.grid(|cell| self.tile_types[self.options(cell).next().unwrap_or(0)])
     }
 }
 
-#[derive(PartialEq, Hash, Eq, Debug, Clone)]
+#[derive(PartialEq, Hash, Eq, Debug, Clone, Copy)]
+#[allow(clippy::upper_case_acronyms)]
 enum Dir {
     WEST,
     NORTH,
@@ -607,53 +1935,17 @@ enum Dir {
     SOUTH,
 }
 
-impl IntoIterator for Dir {
-    type Item = Dir;
-    type IntoIter = std::vec::IntoIter<Dir>;
-
-    fn into_iter(self) -> Self::IntoIter {
-        vec![Dir::NORTH, Dir::SOUTH, Dir::EAST, Dir::WEST].into_iter()
-    }
-}
-
-//this struct defines the rules for a tile type
-#[derive(PartialEq, Hash, Eq, Debug, Clone)]
-struct Tile {
-    coords: (usize, usize),
-    //rep: char,
-    // Tile only has a type once it has been fully collapsed
-    t: Option<usize>,
-    position: Vec<usize>,
-}
-impl Tile {
-    /// Create a fresh tile with
-    /// a full superposition. Subpositions will be removed
-    /// as collapse occurs.
-    fn fresh(coords: (usize, usize), full: Vec<usize>) -> Self {
-        Self {
-            coords,
-            t: None,
-            position: full,
-        }
-    }
-
-    fn set(coords: (usize, usize), kind: usize) -> Self {
-        Self {
-            coords,
-            t: Some(kind),
-            position: Vec::new(),
-        }
-    }
-
-    /// Determine what the entropy of the tile is
-    ///
-    /// Entropy is defined as the number of possible subpositions
-    /// in this tile's superposition, or infinity if it is collapsed.
-    fn entropy(&self) -> usize {
-        if self.t.is_some() {
-            usize::MAX
-        } else {
-            self.position.len()
+impl Dir {
+    /// Every direction, in the same order as their discriminants
+    const ALL: [Dir; 4] = [Dir::WEST, Dir::NORTH, Dir::EAST, Dir::SOUTH];
+
+    /// Direction pointing the other way
+    fn opposite(self) -> Dir {
+        match self {
+            Dir::WEST => Dir::EAST,
+            Dir::NORTH => Dir::SOUTH,
+            Dir::EAST => Dir::WEST,
+            Dir::SOUTH => Dir::NORTH,
         }
     }
 }
@@ -685,3 +1977,239 @@ pub struct Rule {
 // ⠀⠀⠀⠀⠀⠀⠀⣿⣿⠀⠀⠀⠀⠀⣿⣿⡇⠀⢹⣿⡆⠀⠀⠀⣸⣿⠇⠀⠀⠀
 // ⠀⠀⠀⠀⠀⠀⠀⢿⣿⣦⣄⣀⣠⣴⣿⣿⠁⠀⠈⠻⣿⣿⣿⣿⡿⠏⠀⠀⠀⠀
 // ⠀⠀⠀⠀⠀⠀⠀⠈⠛⠻⠿⠿⠿⠿⠋⠁⠀⠀⠀⠀⠀⠀⠀⠀⠀⠀⠀
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::prefabs::PREFABS;
+    use rand::SeedableRng;
+
+    /// 2x2 board over tile types 0, 1 and 2. Going east swaps 0 and 2, going south turns
+    /// 0 into 0, 1 into 1 or 2 and 2 into 0. Every option has a neighbor on every side,
+    /// so propagation alone rules nothing out, but the only way around is all 1s.
+    fn knotted_board() -> Board {
+        let allowed = |tiles: &[usize]| bitset(tiles.iter().copied(), 1);
+        // West, north, east and south of each tile type
+        let propagator = vec![
+            [
+                allowed(&[2]),
+                allowed(&[0, 2]),
+                allowed(&[2]),
+                allowed(&[0]),
+            ],
+            [
+                allowed(&[1]),
+                allowed(&[1]),
+                allowed(&[1]),
+                allowed(&[1, 2]),
+            ],
+            [allowed(&[0]), allowed(&[1]), allowed(&[0]), allowed(&[0])],
+        ];
+        Board::new((2, 2), vec![0, 1, 2], vec![1, 1, 1], propagator)
+    }
+
+    #[test]
+    fn backtracking_rules_out_a_forced_contradiction() {
+        let mut board = knotted_board();
+        assert!(board.propagate((0..4).collect()).is_ok());
+        assert_eq!(board.entropy(0), 3);
+
+        // Collapse the top left cell to 0 by hand, which doesn't fit all the way around
+        board.stack.push(Frame {
+            snapshot: board.positions.clone(),
+            cell: 0,
+            choice: 0,
+        });
+        board.positions[0] = 1;
+        let contradiction = board.propagate(vec![0]).unwrap_err();
+
+        assert_eq!(
+            board.backtrack(contradiction),
+            Step::Backtracked(contradiction)
+        );
+        assert!(board.stack.is_empty());
+        let options = board.options(0).collect::<Vec<_>>();
+        assert!(!options.contains(&0) && options.contains(&1));
+    }
+
+    #[test]
+    fn collapse_backtracks_to_the_only_solution_within_budget() {
+        let budget = WfcBudget {
+            attempts: 1,
+            backtracks: 4,
+            steps: 16,
+            propagations: 100,
+        };
+        let mut backtracked = false;
+        for seed in 0..20 {
+            let mut rng = StdRng::seed_from_u64(seed);
+            let generated = collapse(
+                knotted_board(),
+                None,
+                &ChunkEdges::default(),
+                &HashMap::new(),
+                &mut rng,
+                budget,
+            )
+            .unwrap_or_else(|e| panic!("seed {}: {}", seed, e));
+            assert_eq!(generated.tiles, vec![vec![1, 1], vec![1, 1]]);
+            assert_eq!(generated.stats.attempts, 1);
+            backtracked |= generated.stats.backtracks > 0;
+        }
+        assert!(backtracked, "no seed ran into the contradiction");
+    }
+
+    /// Border cells of `tiles` on the `dir` side, west to east or north to south
+    fn border(tiles: &[Vec<usize>], dir: Dir) -> Vec<usize> {
+        match dir {
+            Dir::WEST => tiles.iter().map(|row| row[0]).collect(),
+            Dir::NORTH => tiles[0].clone(),
+            Dir::EAST => tiles.iter().map(|row| row[MAP_WIDTH - 1]).collect(),
+            Dir::SOUTH => tiles[MAP_HEIGHT - 1].clone(),
+        }
+    }
+
+    #[test]
+    fn chunks_fit_the_neighbor_borders_they_were_generated_against() {
+        // Chunks (1, 0) and (-2, 1) of this world have to relax a side
+        let procgen = ProcGen::new(Path::new(WFC_INPUTS), Path::new(PREFABS), 2).0;
+        let mut chunks = HashMap::new();
+        for y in -2..=2 {
+            for x in -2..=2 {
+                let mut seams = procgen.seams(x, y);
+                procgen.connected_layout(&mut seams, x, y, &mut chunk_rng(procgen.seed, x, y));
+                chunks.insert((x, y), (procgen.generate(x, y), seams.edges));
+            }
+        }
+
+        let mut relaxed = 0;
+        let mut lined_up = 0;
+        for (&(x, y), (layout, edges)) in &chunks {
+            let (_, rules) = procgen.chunk_rules(x, y);
+            let sides = [
+                (Dir::WEST, &edges.west, (x - 1, y)),
+                (Dir::NORTH, &edges.north, (x, y + 1)),
+                (Dir::EAST, &edges.east, (x + 1, y)),
+                (Dir::SOUTH, &edges.south, (x, y - 1)),
+            ];
+            for (dir, edge, neighbor) in sides {
+                let Some((neighbor, _)) = chunks.get(&neighbor) else {
+                    continue;
+                };
+                // A neighbor that relaxed this side doesn't have the border it was made against
+                let across = border(&neighbor.tiles, dir.opposite());
+                if edge.as_ref() != Some(&across) {
+                    continue;
+                }
+                for (t, other) in border(&layout.tiles, dir).into_iter().zip(across) {
+                    assert!(
+                        rules[&t].neighbor_rules[&dir].contains(&other),
+                        "chunk ({}, {}) has {} next to {} on its {:?} side",
+                        x,
+                        y,
+                        t,
+                        other,
+                        dir
+                    );
+                }
+                lined_up += 1;
+                if layout.relaxations > 0 {
+                    relaxed += 1;
+                }
+            }
+        }
+        assert!(
+            lined_up > 0 && relaxed > 0,
+            "no relaxed chunk lined up with a neighbor"
+        );
+    }
+
+    /// Sand that looks the same every way around, a fence end that mirrors into the other end,
+    /// and a sign that has no mirrored counterpart
+    fn fence_tiles() -> TileRegistry {
+        TileRegistry::parse(
+            "sheet tiles.png 4 1\n\
+             0 sand walkable none 100 - all\n\
+             1 fence_left solid none 100 - mirror-x:2\n\
+             2 fence_right solid none 100 - mirror-x:1\n\
+             3 sign solid none 100 -\n",
+        )
+        .unwrap()
+    }
+
+    fn sample(header: &str, board: &str, tiles: &TileRegistry) -> WfcInput {
+        let contents = format!(
+            "waste-wfc 1\ntileset backgrounds/overworld_tiles.txt\n{}board\n{}",
+            header, board
+        );
+        WfcInput::parse(&contents, tiles).unwrap()
+    }
+
+    fn allowed(rules: &HashMap<usize, Rule>, t: usize, dir: Dir) -> Vec<usize> {
+        let mut allowed = rules[&t].neighbor_rules[&dir].clone();
+        allowed.sort_unstable();
+        allowed
+    }
+
+    #[test]
+    fn mirrored_board_teaches_mirrored_rules() {
+        let tiles = fence_tiles();
+        let input = sample("symmetry mirror-x\n", "1 0 3\n", &tiles);
+        let (mut rules, mut freqs) = (HashMap::new(), HashMap::new());
+        rulegen(&input, &tiles, &mut rules, &mut freqs);
+
+        // As written
+        assert_eq!(allowed(&rules, 1, Dir::EAST), vec![0]);
+        assert_eq!(allowed(&rules, 0, Dir::WEST), vec![1]);
+        // Mirrored, the left end turns into the right end on the other side of the sand
+        assert_eq!(allowed(&rules, 0, Dir::EAST), vec![2, 3]);
+        assert_eq!(allowed(&rules, 2, Dir::WEST), vec![0]);
+        assert!(allowed(&rules, 2, Dir::EAST).is_empty());
+        // The sign has nothing to turn into, so it only learns what the board says
+        assert_eq!(allowed(&rules, 3, Dir::WEST), vec![0]);
+        assert!(allowed(&rules, 3, Dir::EAST).is_empty());
+        // Mirrored copies teach rules but don't count towards frequencies
+        assert_eq!(freqs[&0], DEFAULT_SPAWN_WEIGHT);
+        assert!(!freqs.contains_key(&2));
+    }
+
+    #[test]
+    fn overlapping_model_only_copies_windows_of_the_sample() {
+        let tiles = fence_tiles();
+        let input = sample("", "0 3 0 3\n0 3 0 3\n", &tiles);
+        let patterns = patterngen(std::slice::from_ref(&input), 2, &tiles);
+
+        assert_eq!(
+            patterns.patterns,
+            vec![vec![vec![0, 3], vec![0, 3]], vec![vec![3, 0], vec![3, 0]]]
+        );
+        // The window starting on sand shows up twice
+        assert_eq!(patterns.freqs, vec![200, 100]);
+        // West, north, east and south: the two alternate across and repeat down
+        assert_eq!(
+            patterns.compatible,
+            vec![
+                [vec![1], vec![0], vec![1], vec![0]],
+                [vec![0], vec![1], vec![0], vec![1]]
+            ]
+        );
+
+        let (mut rules, mut freqs) = (HashMap::new(), HashMap::new());
+        rulegen(&input, &tiles, &mut rules, &mut freqs);
+        let mut rng = StdRng::seed_from_u64(7);
+        let generated = wfc_overlapping(
+            Some(vec![(3, (0, 0))]),
+            &ChunkEdges::default(),
+            &patterns,
+            &rules,
+            &mut rng,
+            WfcBudget::default(),
+        )
+        .unwrap();
+        for row in &generated.tiles {
+            for (col, t) in row.iter().enumerate() {
+                assert_eq!(*t, if col % 2 == 0 { 3 } else { 0 });
+            }
+        }
+    }
+}
@@ -0,0 +1,384 @@
+use crate::biomes::Biome;
+use crate::tiles::{TileRegistry, DEFAULT_SPAWN_WEIGHT, TILE_REGISTRY};
+use std::collections::HashMap;
+use std::fmt;
+
+/// Current version of the WFC input format
+pub const WFC_INPUT_VERSION: usize = 1;
+/// First word of a versioned WFC input file
+const MAGIC: &str = "waste-wfc";
+
+/// Ways a sample board may be turned around to learn more rules from it
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Symmetry {
+    /// Mirror left to right
+    pub mirror_x: bool,
+    /// Mirror top to bottom
+    pub mirror_y: bool,
+    /// Quarter turns
+    pub rotate: bool,
+}
+
+/// How the chunks of a biome get generated from its sample boards
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum WfcModel {
+    /// Tile by tile, from which tiles were seen next to each other
+    #[default]
+    Tiled,
+    /// From every NxN window of the boards, so structures bigger than
+    /// one tile like roads and buildings stay in one piece
+    Overlapping(usize),
+}
+
+/// One sample board that rules get learned from, with its header.
+///
+/// Version 1 files look like
+/// ```text
+/// waste-wfc 1
+/// biome desert
+/// tileset backgrounds/overworld_tiles.txt
+/// weight 32 50
+/// symmetry mirror-x
+/// model overlapping 3
+/// board
+/// 0 0 32 0
+/// 0 4 0 0
+/// ```
+/// Only the first line, `tileset` and `board` are required. `weight <tile> <percent>` scales
+/// how often a tile counts from this board and may be repeated. `model` is `tiled` (the default)
+/// or `overlapping <N>`, and applies to the whole biome. `#` starts a comment.
+/// Files without a header are read as a bare board, like before the format was versioned.
+#[derive(Debug, Clone)]
+pub struct WfcInput {
+    pub version: usize,
+    /// Biome the board belongs to, if the file says
+    pub biome: Option<Biome>,
+    /// Weight overrides in percent, by tile type
+    pub weights: HashMap<usize, usize>,
+    pub symmetry: Symmetry,
+    pub model: WfcModel,
+    pub board: Vec<Vec<usize>>,
+}
+
+/// Problem found while reading a WFC input file
+#[derive(Debug)]
+pub struct WfcInputError {
+    pub line: usize,
+    pub column: usize,
+    pub message: String,
+}
+
+impl fmt::Display for WfcInputError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}:{}: {}", self.line, self.column, self.message)
+    }
+}
+
+impl WfcInput {
+    /// How much one occurrence of `tile` on this board counts towards its frequency
+    pub fn weight(&self, tile: usize) -> usize {
+        *self.weights.get(&tile).unwrap_or(&DEFAULT_SPAWN_WEIGHT)
+    }
+
+    /// Read a WFC input file's contents, checking every tile against the tile registry
+    pub fn parse(contents: &str, tiles: &TileRegistry) -> Result<Self, WfcInputError> {
+        let mut lines = contents
+            .lines()
+            .enumerate()
+            .map(|(i, line)| (i + 1, words(line)))
+            .filter(|(_, words)| !words.is_empty())
+            .peekable();
+
+        let mut input = WfcInput {
+            version: 0,
+            biome: None,
+            weights: HashMap::new(),
+            symmetry: Symmetry::default(),
+            model: WfcModel::default(),
+            board: Vec::new(),
+        };
+
+        let versioned = lines.peek().is_some_and(|(_, words)| words[0].1 == MAGIC);
+        if versioned {
+            let (line, words) = lines.next().unwrap();
+            let (column, version) = field(line, &words, 1)?;
+            input.version = number(line, column, version)?;
+            if input.version != WFC_INPUT_VERSION {
+                return Err(error(
+                    line,
+                    column,
+                    format!(
+                        "unsupported version {}, expected {}",
+                        input.version, WFC_INPUT_VERSION
+                    ),
+                ));
+            }
+            input.read_header(&mut lines, tiles)?;
+        }
+
+        // Everything left is the board itself
+        for (line, words) in lines {
+            let row = words
+                .iter()
+                .map(|(column, word)| tile(line, *column, word, tiles))
+                .collect::<Result<Vec<usize>, WfcInputError>>()?;
+            if let Some(first) = input.board.first() {
+                if row.len() != first.len() {
+                    // Point at the first extra tile, or just past the end of a short row
+                    let (last_column, last) = words[words.len() - 1];
+                    let column = words
+                        .get(first.len())
+                        .map_or(last_column + last.chars().count(), |w| w.0);
+                    return Err(error(
+                        line,
+                        column,
+                        format!(
+                            "row is {} tiles wide, but the first row is {}",
+                            row.len(),
+                            first.len()
+                        ),
+                    ));
+                }
+            }
+            input.board.push(row);
+        }
+
+        if input.board.is_empty() {
+            return Err(error(
+                contents.lines().count().max(1),
+                1,
+                "board is empty".to_string(),
+            ));
+        }
+        Ok(input)
+    }
+
+    /// Read header lines up to and including the `board` line
+    fn read_header<'a>(
+        &mut self,
+        lines: &mut impl Iterator<Item = (usize, Vec<(usize, &'a str)>)>,
+        tiles: &TileRegistry,
+    ) -> Result<(), WfcInputError> {
+        let mut tileset = false;
+        let mut last_line = 0;
+        for (line, words) in lines.by_ref() {
+            last_line = line;
+            let (column, key) = words[0];
+            match key {
+                "board" => {
+                    if !tileset {
+                        return Err(error(line, column, "missing `tileset` line".to_string()));
+                    }
+                    return Ok(());
+                }
+                "biome" => {
+                    let (column, name) = field(line, &words, 1)?;
+                    self.biome = Some(
+                        Biome::ALL
+                            .iter()
+                            .copied()
+                            .find(|b| b.name() == name)
+                            .ok_or_else(|| {
+                                error(line, column, format!("unknown biome `{}`", name))
+                            })?,
+                    );
+                }
+                "tileset" => {
+                    let (column, path) = field(line, &words, 1)?;
+                    // Tile numbers only mean anything for the tilesheet they were written for
+                    if path != TILE_REGISTRY {
+                        return Err(error(
+                            line,
+                            column,
+                            format!("board is for tileset `{}`, not `{}`", path, TILE_REGISTRY),
+                        ));
+                    }
+                    tileset = true;
+                }
+                "weight" => {
+                    let (column, t) = field(line, &words, 1)?;
+                    let t = tile(line, column, t, tiles)?;
+                    let (column, weight) = field(line, &words, 2)?;
+                    self.weights.insert(t, number(line, column, weight)?);
+                }
+                "symmetry" => {
+                    for (column, flag) in &words[1..] {
+                        match *flag {
+                            "none" => {}
+                            "mirror-x" => self.symmetry.mirror_x = true,
+                            "mirror-y" => self.symmetry.mirror_y = true,
+                            "rotate" => self.symmetry.rotate = true,
+                            _ => {
+                                return Err(error(
+                                    line,
+                                    *column,
+                                    format!("unknown symmetry `{}`", flag),
+                                ))
+                            }
+                        }
+                    }
+                }
+                "model" => {
+                    let (column, name) = field(line, &words, 1)?;
+                    self.model = match name {
+                        "tiled" => WfcModel::Tiled,
+                        "overlapping" => {
+                            let (column, size) = field(line, &words, 2)?;
+                            let size = number(line, column, size)?;
+                            // A single tile pattern knows nothing about its neighbors
+                            if size < 2 {
+                                return Err(error(
+                                    line,
+                                    column,
+                                    format!("pattern size must be at least 2, found {}", size),
+                                ));
+                            }
+                            WfcModel::Overlapping(size)
+                        }
+                        _ => return Err(error(line, column, format!("unknown model `{}`", name))),
+                    };
+                }
+                _ => return Err(error(line, column, format!("unknown header `{}`", key))),
+            }
+        }
+
+        Err(error(
+            last_line + 1,
+            1,
+            "header never ends, missing `board` line".to_string(),
+        ))
+    }
+}
+
+/// Split a line into words and the (1-based) column each one starts at, dropping comments
+pub(crate) fn words(line: &str) -> Vec<(usize, &str)> {
+    let code = line.split('#').next().unwrap_or("");
+    let mut words = Vec::new();
+    let mut start = None;
+    for (i, c) in code.char_indices() {
+        match (c.is_whitespace(), start) {
+            (false, None) => start = Some(i),
+            (true, Some(s)) => {
+                words.push((code[..s].chars().count() + 1, &code[s..i]));
+                start = None;
+            }
+            _ => {}
+        }
+    }
+    if let Some(s) = start {
+        words.push((code[..s].chars().count() + 1, &code[s..]));
+    }
+    words
+}
+
+pub(crate) fn error(line: usize, column: usize, message: String) -> WfcInputError {
+    WfcInputError {
+        line,
+        column,
+        message,
+    }
+}
+
+/// Get the `index`th word of a line, or complain that it is missing
+pub(crate) fn field<'a>(
+    line: usize,
+    words: &[(usize, &'a str)],
+    index: usize,
+) -> Result<(usize, &'a str), WfcInputError> {
+    words.get(index).copied().ok_or_else(|| {
+        let (column, last) = words[words.len() - 1];
+        error(
+            line,
+            column + last.chars().count(),
+            format!("`{}` is missing a value", words[0].1),
+        )
+    })
+}
+
+pub(crate) fn number(line: usize, column: usize, word: &str) -> Result<usize, WfcInputError> {
+    word.parse::<usize>()
+        .map_err(|_| error(line, column, format!("expected a number, found `{}`", word)))
+}
+
+/// Parse a tile type and make sure the tile registry knows it
+pub(crate) fn tile(
+    line: usize,
+    column: usize,
+    word: &str,
+    tiles: &TileRegistry,
+) -> Result<usize, WfcInputError> {
+    let t = number(line, column, word)?;
+    if tiles.get(t).is_none() {
+        return Err(error(
+            line,
+            column,
+            format!("tile {} is not in the tile registry", t),
+        ));
+    }
+    Ok(t)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HEADER: &str = "waste-wfc 1\ntileset backgrounds/overworld_tiles.txt\nboard\n";
+
+    fn parse_error(contents: &str) -> WfcInputError {
+        WfcInput::parse(contents, &TileRegistry::default()).expect_err("input should not parse")
+    }
+
+    fn position(e: &WfcInputError) -> (usize, usize) {
+        (e.line, e.column)
+    }
+
+    #[test]
+    fn reads_versioned_board() {
+        let contents = format!("{}# comment\n0 0 4\n0 9  0\n", HEADER);
+        let input = WfcInput::parse(&contents, &TileRegistry::default()).unwrap();
+        assert_eq!(input.version, WFC_INPUT_VERSION);
+        assert_eq!(input.board, vec![vec![0, 0, 4], vec![0, 9, 0]]);
+    }
+
+    #[test]
+    fn rejects_unsupported_version() {
+        let e = parse_error("waste-wfc 2\ntileset backgrounds/overworld_tiles.txt\nboard\n0\n");
+        assert_eq!(position(&e), (1, 11));
+        assert!(e.message.contains("unsupported version 2"), "{}", e);
+    }
+
+    #[test]
+    fn rejects_version_that_is_not_a_number() {
+        let e = parse_error("waste-wfc one\nboard\n0\n");
+        assert_eq!(position(&e), (1, 11));
+    }
+
+    #[test]
+    fn points_past_the_end_of_a_short_row() {
+        let e = parse_error(&format!("{}0 0 0\n0 0\n", HEADER));
+        assert_eq!(position(&e), (5, 4));
+        assert!(e.message.contains("2 tiles wide"), "{}", e);
+    }
+
+    #[test]
+    fn points_at_the_first_extra_tile_of_a_long_row() {
+        let e = parse_error(&format!("{}0 0\n0  0  0 0\n", HEADER));
+        assert_eq!(position(&e), (5, 7));
+        assert!(e.message.contains("4 tiles wide"), "{}", e);
+    }
+
+    #[test]
+    fn rejects_unknown_tiles() {
+        let e = parse_error("0 0\n\n0 99\n");
+        assert_eq!(position(&e), (3, 3));
+        assert!(e.message.contains("tile 99"), "{}", e);
+    }
+
+    #[test]
+    fn rejects_unknown_tiles_in_weights() {
+        let e = parse_error(
+            "waste-wfc 1\ntileset backgrounds/overworld_tiles.txt\nweight 99 50\nboard\n0\n",
+        );
+        assert_eq!(position(&e), (3, 8));
+    }
+}
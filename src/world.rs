@@ -1,12 +1,15 @@
 use bevy::prelude::*;
 
 use crate::{
-    backgrounds::{WIN_H, WIN_W},
+    backgrounds::{CHUNK_HEIGHT, CHUNK_WIDTH, TILE_SIZE},
     monster::{Element, MonsterStats},
     quests::*,
-    Chunk,
+    Chunk, TilePosition,
 };
-use std::collections::{HashMap, VecDeque};
+use std::collections::{HashMap, HashSet, VecDeque};
+use waste::biomes::Biome;
+use waste::tiles::TileRegistry;
+use waste::wfc::{MAP_HEIGHT, MAP_WIDTH};
 
 /// Number of total consumable item types
 pub(crate) const NUM_ITEM_TYPES: usize = 2;
@@ -31,6 +34,11 @@ pub(crate) struct WorldMap {
     // which is GREAT
     // so here it is:
     pub(crate) chunk_components: HashMap<usize, Chunk>,
+    // chunks that are still being generated in the background,
+    // so we don't start generating them a second time
+    pub(crate) pending: HashSet<(isize, isize)>,
+    // chunks whose tile sprites are currently spawned
+    pub(crate) drawn: HashSet<(isize, isize)>,
 }
 
 impl WorldMap {
@@ -42,6 +50,74 @@ impl WorldMap {
         self.chunk_components.insert(id as usize, chunk);
     }
 
+    /// Check if the chunk at (x, y) exists or is being generated
+    pub(crate) fn is_generated_or_pending(&self, x: isize, y: isize) -> bool {
+        self.chunk_ids.contains_key(&(x, y)) || self.pending.contains(&(x, y))
+    }
+
+    /// Remember that the special tile at `position` was used, so it stays used when redrawn
+    pub(crate) fn use_special(&mut self, position: TilePosition) {
+        let (x, y) = position.chunk;
+        if let Some(chunk) = self
+            .chunk_ids
+            .get(&(x, y))
+            .and_then(|id| self.chunk_components.get_mut(id))
+        {
+            chunk.used_specials.insert((position.row, position.col));
+        }
+    }
+
+    /// Mark every tile within `radius` tiles of rendering position (x, y) as seen.
+    ///
+    /// Returns the tiles that hadn't been seen before,
+    /// and the number of chunks that had none of their tiles seen before.
+    pub(crate) fn reveal(&mut self, x: f32, y: f32, radius: usize) -> (Vec<TilePosition>, usize) {
+        let radius = radius as isize;
+        let mut revealed = Vec::new();
+        let mut discovered = 0;
+        for dy in -radius..=radius {
+            for dx in -radius..=radius {
+                if dx * dx + dy * dy > radius * radius {
+                    continue;
+                }
+                let position =
+                    rendering_to_tile(x + dx as f32 * TILE_SIZE, y + dy as f32 * TILE_SIZE);
+                let Some(chunk) = self
+                    .chunk_ids
+                    .get(&position.chunk)
+                    .and_then(|id| self.chunk_components.get_mut(id))
+                else {
+                    continue;
+                };
+                if chunk.seen.is_empty() {
+                    discovered += 1;
+                }
+                if chunk.seen.insert((position.row, position.col)) {
+                    revealed.push(position);
+                }
+            }
+        }
+        (revealed, discovered)
+    }
+
+    /// Check if the player can stand at rendering position (x, y).
+    /// Chunks that haven't been generated yet can't be walked into.
+    pub(crate) fn walkable_at(&self, tiles: &TileRegistry, x: f32, y: f32) -> bool {
+        let position = rendering_to_tile(x, y);
+        self.chunk_ids
+            .get(&position.chunk)
+            .and_then(|id| self.chunk_components.get(id))
+            .is_some_and(|chunk| tiles.walkable(chunk.tiles[position.row][position.col]))
+    }
+
+    /// Biome of the generated chunk at logical position (x, y)
+    pub(crate) fn biome(&self, x: isize, y: isize) -> Option<Biome> {
+        self.chunk_ids
+            .get(&(x, y))
+            .and_then(|id| self.chunk_components.get(id))
+            .map(|chunk| chunk.biome)
+    }
+
     pub(crate) fn get_chunk(&self, x: isize, y: isize) -> Option<Chunk> {
         let id = self.chunk_ids.get(&(x, y));
         if let Some(id) = id {
@@ -54,11 +130,28 @@ impl WorldMap {
 }
 
 pub(crate) fn logical_to_rendering(x: isize, y: isize) -> (f32, f32) {
-    (x as f32 * WIN_W, y as f32 * WIN_H)
+    (x as f32 * CHUNK_WIDTH, y as f32 * CHUNK_HEIGHT)
 }
 
+/// Find the chunk that rendering position (x, y) falls on
 pub(crate) fn rendering_to_logical(x: f32, y: f32) -> (isize, isize) {
-    ((x / WIN_W).trunc() as isize, (y / WIN_H).trunc() as isize)
+    // Chunks are drawn centered on their rendering position
+    (
+        ((x + CHUNK_WIDTH / 2.) / CHUNK_WIDTH).floor() as isize,
+        ((y + CHUNK_HEIGHT / 2.) / CHUNK_HEIGHT).floor() as isize,
+    )
+}
+
+/// Find the chunk and tile that rendering position (x, y) falls on
+pub(crate) fn rendering_to_tile(x: f32, y: f32) -> TilePosition {
+    let chunk = rendering_to_logical(x, y);
+    let from_left = x - (chunk.0 as f32 * CHUNK_WIDTH - CHUNK_WIDTH / 2.);
+    let from_top = (chunk.1 as f32 * CHUNK_HEIGHT + CHUNK_HEIGHT / 2.) - y;
+    TilePosition {
+        chunk,
+        row: ((from_top / TILE_SIZE) as usize).min(MAP_HEIGHT - 1),
+        col: ((from_left / TILE_SIZE) as usize).min(MAP_WIDTH - 1),
+    }
 }
 
 pub(crate) struct GameProgress {
@@ -102,6 +195,10 @@ pub(crate) struct GameProgress {
     pub(crate) spec_moves_left: Vec<usize>,
     /// Active player quests
     pub(crate) quests_active: Vec<Quest>,
+    /// Number of chunks the player has seen at least one tile of
+    pub(crate) chunks_discovered: usize,
+    /// How far the player has walked in the overworld, in tiles
+    pub(crate) distance_travelled: f32,
 }
 
 impl GameProgress {
@@ -224,6 +321,8 @@ impl Default for GameProgress {
             turns_left_of_buff: vec![0; 3],
             spec_moves_left: vec![SPECIALS_PER_BATTLE; 2],
             quests_active: Vec::new(),
+            chunks_discovered: Default::default(),
+            distance_travelled: Default::default(),
         }
     }
 }